@@ -0,0 +1,13 @@
+use thiserror::{self, Error};
+
+use super::Sort;
+
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum ExprError {
+    #[error("{op} cannot be applied to arguments of sort {args:?}")]
+    IllSorted { op: String, args: Vec<Sort> },
+    #[error("Extract [{high}:{low}] out of range for a bitvector of width {width}")]
+    ExtractOutOfRange { high: u32, low: u32, width: u32 },
+    #[error("Bitvectors must be at least one bit wide")]
+    ZeroWidth,
+}
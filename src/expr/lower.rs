@@ -0,0 +1,183 @@
+use std::collections::HashMap;
+
+use z3::ast::{Array, Ast, Bool, Dynamic, Int, BV};
+use z3::{Context, FuncDecl};
+
+use super::{Expr, Node, Op, Sort};
+
+pub fn sort_to_z3<'a>(sort: &Sort, ctx: &'a Context) -> z3::Sort<'a> {
+    match sort {
+        Sort::Bool => z3::Sort::bool(ctx),
+        Sort::Int => z3::Sort::int(ctx),
+        Sort::BitVec(width) => z3::Sort::bitvector(ctx, *width),
+        Sort::Array(index, value) => {
+            z3::Sort::array(ctx, &sort_to_z3(index, ctx), &sort_to_z3(value, ctx))
+        }
+    }
+}
+
+// Translates expressions into z3 terms. Subterms shared between the expressions lowered by
+// one `Lowering` are translated once.
+pub struct Lowering<'a> {
+    ctx: &'a Context,
+    // Keeps each lowered expression alive so that its address is not reused
+    done: HashMap<*const Node, (Expr, Dynamic<'a>)>,
+}
+
+impl<'a> Lowering<'a> {
+    pub fn new(ctx: &'a Context) -> Self {
+        Self {
+            ctx,
+            done: HashMap::new(),
+        }
+    }
+
+    pub fn lower(&mut self, expr: &Expr) -> Dynamic<'a> {
+        let key = std::rc::Rc::as_ptr(&expr.0);
+        if let Some((_, lowered)) = self.done.get(&key) {
+            return lowered.clone();
+        }
+        let args = expr
+            .args()
+            .iter()
+            .map(|arg| self.lower(arg))
+            .collect::<Vec<_>>();
+        let lowered = self.apply(expr, &args);
+        self.done.insert(key, (expr.clone(), lowered.clone()));
+        lowered
+    }
+
+    fn apply(&self, expr: &Expr, args: &[Dynamic<'a>]) -> Dynamic<'a> {
+        let ctx = self.ctx;
+        // Arguments were sort checked when the expression was built
+        let bool = |i: usize| args[i].as_bool().expect("Bool argument");
+        let int = |i: usize| args[i].as_int().expect("Int argument");
+        let bv = |i: usize| args[i].as_bv().expect("bitvector argument");
+        let is_int = || matches!(expr.args()[0].sort(), Sort::Int);
+
+        match expr.op() {
+            Op::Var(name) => match expr.sort() {
+                Sort::Bool => Bool::new_const(ctx, name.as_str()).into(),
+                Sort::Int => Int::new_const(ctx, name.as_str()).into(),
+                Sort::BitVec(width) => BV::new_const(ctx, name.as_str(), *width).into(),
+                Sort::Array(index, value) => Array::new_const(
+                    ctx,
+                    name.as_str(),
+                    &sort_to_z3(index, ctx),
+                    &sort_to_z3(value, ctx),
+                )
+                .into(),
+            },
+            Op::Bool(value) => Bool::from_bool(ctx, *value).into(),
+            Op::Int(value) => Int::from_str(ctx, &value.to_string())
+                .expect("decimal numeral")
+                .into(),
+            Op::BitVec(limbs) => {
+                let Sort::BitVec(width) = expr.sort() else {
+                    unreachable!("bitvector constant of sort {:?}", expr.sort())
+                };
+                // Most significant limb first; it holds whatever bits are left over
+                let top = width - 64 * (limbs.len() as u32 - 1);
+                limbs[..limbs.len() - 1]
+                    .iter()
+                    .rev()
+                    .fold(
+                        BV::from_u64(ctx, limbs[limbs.len() - 1], top),
+                        |high, limb| high.concat(&BV::from_u64(ctx, *limb, 64)),
+                    )
+                    .into()
+            }
+            Op::ConstArray(index) => {
+                Array::const_array(ctx, &sort_to_z3(index, ctx), &args[0]).into()
+            }
+            Op::Not => bool(0).not().into(),
+            Op::And => Bool::and(ctx, &[&bool(0), &bool(1)]).into(),
+            Op::Or => Bool::or(ctx, &[&bool(0), &bool(1)]).into(),
+            Op::Xor => bool(0).xor(&bool(1)).into(),
+            Op::Implies => bool(0).implies(&bool(1)).into(),
+            Op::Eq => args[0]._eq(&args[1]).into(),
+            Op::Ite => bool(0).ite(&args[1], &args[2]),
+            Op::Add if is_int() => Int::add(ctx, &[&int(0), &int(1)]).into(),
+            Op::Add => bv(0).bvadd(&bv(1)).into(),
+            Op::Sub if is_int() => Int::sub(ctx, &[&int(0), &int(1)]).into(),
+            Op::Sub => bv(0).bvsub(&bv(1)).into(),
+            Op::Mul if is_int() => Int::mul(ctx, &[&int(0), &int(1)]).into(),
+            Op::Mul => bv(0).bvmul(&bv(1)).into(),
+            Op::Div if is_int() => int(0).div(&int(1)).into(),
+            Op::Div => bv(0).bvudiv(&bv(1)).into(),
+            Op::Rem if is_int() => int(0).modulo(&int(1)).into(),
+            Op::Rem => bv(0).bvurem(&bv(1)).into(),
+            Op::Sdiv => bv(0).bvsdiv(&bv(1)).into(),
+            Op::Srem => bv(0).bvsrem(&bv(1)).into(),
+            Op::Smod => bv(0).bvsmod(&bv(1)).into(),
+            Op::Neg if is_int() => int(0).unary_minus().into(),
+            Op::Neg => bv(0).bvneg().into(),
+            Op::Lt if is_int() => int(0).lt(&int(1)).into(),
+            Op::Lt => bv(0).bvult(&bv(1)).into(),
+            Op::Le if is_int() => int(0).le(&int(1)).into(),
+            Op::Le => bv(0).bvule(&bv(1)).into(),
+            Op::Gt if is_int() => int(0).gt(&int(1)).into(),
+            Op::Gt => bv(0).bvugt(&bv(1)).into(),
+            Op::Ge if is_int() => int(0).ge(&int(1)).into(),
+            Op::Ge => bv(0).bvuge(&bv(1)).into(),
+            Op::Slt => bv(0).bvslt(&bv(1)).into(),
+            Op::Sle => bv(0).bvsle(&bv(1)).into(),
+            Op::Sgt => bv(0).bvsgt(&bv(1)).into(),
+            Op::Sge => bv(0).bvsge(&bv(1)).into(),
+            Op::BvNot => bv(0).bvnot().into(),
+            Op::BvAnd => bv(0).bvand(&bv(1)).into(),
+            Op::BvOr => bv(0).bvor(&bv(1)).into(),
+            Op::BvXor => bv(0).bvxor(&bv(1)).into(),
+            Op::Shl => bv(0).bvshl(&bv(1)).into(),
+            Op::Lshr => bv(0).bvlshr(&bv(1)).into(),
+            Op::Ashr => bv(0).bvashr(&bv(1)).into(),
+            Op::Concat => bv(0).concat(&bv(1)).into(),
+            Op::Extract(high, low) => bv(0).extract(*high, *low).into(),
+            Op::ZeroExt(bits) => bv(0).zero_ext(*bits).into(),
+            Op::SignExt(bits) => bv(0).sign_ext(*bits).into(),
+            Op::BvToInt => bv(0).to_int(false).into(),
+            Op::IntToBv(width) => BV::from_int(&int(0), *width).into(),
+            Op::Select => args[0].as_array().expect("array argument").select(&args[1]),
+            Op::Store => args[0]
+                .as_array()
+                .expect("array argument")
+                .store(&args[1], &args[2])
+                .into(),
+            Op::Apply(name, range) => {
+                let domain = expr
+                    .args()
+                    .iter()
+                    .map(|arg| sort_to_z3(arg.sort(), ctx))
+                    .collect::<Vec<_>>();
+                let decl = FuncDecl::new(
+                    ctx,
+                    name.as_str(),
+                    &domain.iter().collect::<Vec<_>>(),
+                    &sort_to_z3(range, ctx),
+                );
+                decl.apply(&args.iter().map(|arg| arg as &dyn Ast).collect::<Vec<_>>())
+            }
+        }
+    }
+}
+
+impl Expr {
+    pub fn to_z3<'a>(&self, ctx: &'a Context) -> Dynamic<'a> {
+        Lowering::new(ctx).lower(self)
+    }
+
+    // Panics unless the expression is a Bool
+    pub fn to_z3_bool<'a>(&self, ctx: &'a Context) -> Bool<'a> {
+        self.to_z3(ctx).as_bool().expect("Bool expression")
+    }
+
+    // Panics unless the expression is an Int
+    pub fn to_z3_int<'a>(&self, ctx: &'a Context) -> Int<'a> {
+        self.to_z3(ctx).as_int().expect("Int expression")
+    }
+
+    // Panics unless the expression is a bitvector
+    pub fn to_z3_bv<'a>(&self, ctx: &'a Context) -> BV<'a> {
+        self.to_z3(ctx).as_bv().expect("bitvector expression")
+    }
+}
@@ -0,0 +1,568 @@
+pub mod error;
+#[cfg(feature = "z3")]
+pub mod lower;
+pub mod model;
+pub mod simplify;
+pub mod smtlib;
+
+use std::cell::Cell;
+use std::collections::{BTreeSet, HashMap};
+use std::ops::{Add, Mul, Neg, Not, Sub};
+use std::rc::Rc;
+
+use error::ExprError;
+
+pub type ExprResult<T> = Result<T, ExprError>;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Sort {
+    Bool,
+    Int,
+    BitVec(u32),
+    // Index and value sorts
+    Array(Box<Sort>, Box<Sort>),
+}
+
+impl Sort {
+    pub fn array(index: Sort, value: Sort) -> Self {
+        Sort::Array(Box::new(index), Box::new(value))
+    }
+
+    // Rejects zero-width bitvectors, including inside array sorts
+    pub fn check(&self) -> ExprResult<()> {
+        match self {
+            Sort::BitVec(0) => Err(ExprError::ZeroWidth),
+            Sort::Array(index, value) => index.check().and(value.check()),
+            _ => Ok(()),
+        }
+    }
+}
+
+// The operator at the root of an expression. Variables and constants are operators without
+// arguments.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Op {
+    Var(String),
+    Bool(bool),
+    Int(i128),
+    // Little-endian 64 bit limbs, masked to the width of the sort
+    BitVec(Vec<u64>),
+    // An array over the given index sort holding its argument at every index
+    ConstArray(Sort),
+    Not,
+    And,
+    Or,
+    Xor,
+    Implies,
+    Eq,
+    Ite,
+    // Arithmetic on Int, or modular arithmetic on bitvectors of one width. Division and
+    // remainder are unsigned on bitvectors; remainder is the Euclidean modulus on Int.
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Neg,
+    // Signed bitvector division. The remainder takes the sign of the dividend for Srem and of
+    // the divisor for Smod.
+    Sdiv,
+    Srem,
+    Smod,
+    // Unsigned on bitvectors
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    // Signed bitvector comparisons
+    Slt,
+    Sle,
+    Sgt,
+    Sge,
+    BvNot,
+    BvAnd,
+    BvOr,
+    BvXor,
+    Shl,
+    Lshr,
+    Ashr,
+    Concat,
+    // Bits high down to low, inclusive
+    Extract(u32, u32),
+    ZeroExt(u32),
+    SignExt(u32),
+    // Unsigned value of a bitvector
+    BvToInt,
+    // An Int modulo 2^width
+    IntToBv(u32),
+    Select,
+    Store,
+    // An uninterpreted function of the given range sort, applied to its arguments
+    Apply(String, Sort),
+}
+
+#[derive(Debug, PartialEq, Eq, Hash)]
+struct Node {
+    op: Op,
+    args: Vec<Expr>,
+    sort: Sort,
+}
+
+// A sorted symbolic expression independent of any solver. Subterms are shared, so clones
+// are cheap; equality and hashing are structural. Both `Debug` and `Display` print it as an
+// SMT-LIB2 term.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct Expr(Rc<Node>);
+
+thread_local! {
+    // Expressions are not `Send`, so a counter per thread keeps fresh names unique
+    static FRESH: Cell<usize> = const { Cell::new(0) };
+}
+
+impl Expr {
+    // A variable named `prefix!n`, a name no other call on this thread returns
+    pub fn fresh_var(prefix: &str, sort: Sort) -> Self {
+        let n = FRESH.with(|fresh| fresh.replace(fresh.get() + 1));
+        Self::var(format!("{prefix}!{n}"), sort)
+    }
+
+    pub fn var(name: impl Into<String>, sort: Sort) -> Self {
+        sort.check().unwrap_or_else(|e| panic!("{e}"));
+        Self::node(Op::Var(name.into()), vec![], sort)
+    }
+
+    pub fn bool(value: bool) -> Self {
+        Self::node(Op::Bool(value), vec![], Sort::Bool)
+    }
+
+    pub fn int(value: impl Into<i128>) -> Self {
+        Self::node(Op::Int(value.into()), vec![], Sort::Int)
+    }
+
+    pub fn bv(value: u64, width: u32) -> Self {
+        Self::bv_from_limbs(&[value], width)
+    }
+
+    // `limbs` are little-endian; bits past `width` are dropped
+    pub fn bv_from_limbs(limbs: &[u64], width: u32) -> Self {
+        if width == 0 {
+            panic!("{}", ExprError::ZeroWidth);
+        }
+        let len = width.div_ceil(64) as usize;
+        let mut limbs = limbs.to_vec();
+        limbs.resize(len, 0);
+        if !width.is_multiple_of(64) {
+            limbs[len - 1] &= (1 << (width % 64)) - 1;
+        }
+        Self::node(Op::BitVec(limbs), vec![], Sort::BitVec(width))
+    }
+
+    pub fn const_array(index: Sort, value: &Expr) -> Self {
+        Self::apply(Op::ConstArray(index), vec![value.clone()])
+    }
+
+    // Applies `op` to `args`, checking that they are well-sorted
+    pub fn app(op: Op, args: Vec<Expr>) -> ExprResult<Self> {
+        let sort = result_sort(&op, &args)?;
+        Ok(Self::node(op, args, sort))
+    }
+
+    fn node(op: Op, args: Vec<Expr>, sort: Sort) -> Self {
+        Self(Rc::new(Node { op, args, sort }))
+    }
+
+    // The builder methods below panic on ill-sorted arguments, as z3's do. Use `Expr::app`
+    // to get an error instead.
+    fn apply(op: Op, args: Vec<Expr>) -> Self {
+        Self::app(op, args).unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    pub fn op(&self) -> &Op {
+        &self.0.op
+    }
+
+    pub fn args(&self) -> &[Expr] {
+        &self.0.args
+    }
+
+    pub fn sort(&self) -> &Sort {
+        &self.0.sort
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self.op() {
+            Op::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_int(&self) -> Option<i128> {
+        match self.op() {
+            Op::Int(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    // Value of an Int, or of a bitvector constant that fits in 64 bits
+    pub fn as_u64(&self) -> Option<u64> {
+        match self.op() {
+            Op::Int(value) => u64::try_from(*value).ok(),
+            Op::BitVec(limbs) if limbs[1..].iter().all(|&limb| limb == 0) => Some(limbs[0]),
+            _ => None,
+        }
+    }
+
+    pub fn not(&self) -> Self {
+        Self::apply(Op::Not, vec![self.clone()])
+    }
+
+    pub fn and(&self, other: &Expr) -> Self {
+        self.binary(Op::And, other)
+    }
+
+    pub fn or(&self, other: &Expr) -> Self {
+        self.binary(Op::Or, other)
+    }
+
+    pub fn xor(&self, other: &Expr) -> Self {
+        self.binary(Op::Xor, other)
+    }
+
+    pub fn implies(&self, other: &Expr) -> Self {
+        self.binary(Op::Implies, other)
+    }
+
+    pub fn _eq(&self, other: &Expr) -> Self {
+        self.binary(Op::Eq, other)
+    }
+
+    pub fn ite(&self, then: &Expr, otherwise: &Expr) -> Self {
+        Self::apply(Op::Ite, vec![self.clone(), then.clone(), otherwise.clone()])
+    }
+
+    pub fn add(&self, other: &Expr) -> Self {
+        self.binary(Op::Add, other)
+    }
+
+    pub fn sub(&self, other: &Expr) -> Self {
+        self.binary(Op::Sub, other)
+    }
+
+    pub fn mul(&self, other: &Expr) -> Self {
+        self.binary(Op::Mul, other)
+    }
+
+    pub fn div(&self, other: &Expr) -> Self {
+        self.binary(Op::Div, other)
+    }
+
+    pub fn rem(&self, other: &Expr) -> Self {
+        self.binary(Op::Rem, other)
+    }
+
+    pub fn sdiv(&self, other: &Expr) -> Self {
+        self.binary(Op::Sdiv, other)
+    }
+
+    pub fn srem(&self, other: &Expr) -> Self {
+        self.binary(Op::Srem, other)
+    }
+
+    pub fn smod(&self, other: &Expr) -> Self {
+        self.binary(Op::Smod, other)
+    }
+
+    pub fn neg(&self) -> Self {
+        Self::apply(Op::Neg, vec![self.clone()])
+    }
+
+    pub fn lt(&self, other: &Expr) -> Self {
+        self.binary(Op::Lt, other)
+    }
+
+    pub fn le(&self, other: &Expr) -> Self {
+        self.binary(Op::Le, other)
+    }
+
+    pub fn gt(&self, other: &Expr) -> Self {
+        self.binary(Op::Gt, other)
+    }
+
+    pub fn ge(&self, other: &Expr) -> Self {
+        self.binary(Op::Ge, other)
+    }
+
+    pub fn slt(&self, other: &Expr) -> Self {
+        self.binary(Op::Slt, other)
+    }
+
+    pub fn sle(&self, other: &Expr) -> Self {
+        self.binary(Op::Sle, other)
+    }
+
+    pub fn sgt(&self, other: &Expr) -> Self {
+        self.binary(Op::Sgt, other)
+    }
+
+    pub fn sge(&self, other: &Expr) -> Self {
+        self.binary(Op::Sge, other)
+    }
+
+    pub fn bvnot(&self) -> Self {
+        Self::apply(Op::BvNot, vec![self.clone()])
+    }
+
+    pub fn bvand(&self, other: &Expr) -> Self {
+        self.binary(Op::BvAnd, other)
+    }
+
+    pub fn bvor(&self, other: &Expr) -> Self {
+        self.binary(Op::BvOr, other)
+    }
+
+    pub fn bvxor(&self, other: &Expr) -> Self {
+        self.binary(Op::BvXor, other)
+    }
+
+    pub fn shl(&self, other: &Expr) -> Self {
+        self.binary(Op::Shl, other)
+    }
+
+    pub fn lshr(&self, other: &Expr) -> Self {
+        self.binary(Op::Lshr, other)
+    }
+
+    pub fn ashr(&self, other: &Expr) -> Self {
+        self.binary(Op::Ashr, other)
+    }
+
+    // `self` becomes the high bits
+    pub fn concat(&self, other: &Expr) -> Self {
+        self.binary(Op::Concat, other)
+    }
+
+    pub fn extract(&self, high: u32, low: u32) -> Self {
+        Self::apply(Op::Extract(high, low), vec![self.clone()])
+    }
+
+    pub fn zero_ext(&self, bits: u32) -> Self {
+        Self::apply(Op::ZeroExt(bits), vec![self.clone()])
+    }
+
+    pub fn sign_ext(&self, bits: u32) -> Self {
+        Self::apply(Op::SignExt(bits), vec![self.clone()])
+    }
+
+    pub fn bv_to_int(&self) -> Self {
+        Self::apply(Op::BvToInt, vec![self.clone()])
+    }
+
+    pub fn int_to_bv(&self, width: u32) -> Self {
+        Self::apply(Op::IntToBv(width), vec![self.clone()])
+    }
+
+    pub fn select(&self, index: &Expr) -> Self {
+        self.binary(Op::Select, index)
+    }
+
+    pub fn store(&self, index: &Expr, value: &Expr) -> Self {
+        Self::apply(Op::Store, vec![self.clone(), index.clone(), value.clone()])
+    }
+
+    // Applies the uninterpreted function `name`, whose domain is given by the sorts of `args`
+    pub fn call(name: impl Into<String>, args: &[Expr], range: Sort) -> Self {
+        Self::apply(Op::Apply(name.into(), range), args.to_vec())
+    }
+
+    fn binary(&self, op: Op, other: &Expr) -> Self {
+        Self::apply(op, vec![self.clone(), other.clone()])
+    }
+
+    // Every variable occurring in the expression, with its sort
+    pub fn vars(&self) -> BTreeSet<(String, Sort)> {
+        let mut vars = BTreeSet::new();
+        self.visit(|expr| {
+            if let Op::Var(name) = expr.op() {
+                vars.insert((name.clone(), expr.sort().clone()));
+            }
+        });
+        vars
+    }
+
+    // Every uninterpreted function applied in the expression, with its domain and range
+    pub fn functions(&self) -> BTreeSet<(String, Vec<Sort>, Sort)> {
+        let mut functions = BTreeSet::new();
+        self.visit(|expr| {
+            if let Op::Apply(name, range) = expr.op() {
+                let domain = expr.args().iter().map(|arg| arg.sort().clone()).collect();
+                functions.insert((name.clone(), domain, range.clone()));
+            }
+        });
+        functions
+    }
+
+    // Calls `f` once on every distinct subterm
+    pub(crate) fn visit<'e>(&'e self, mut f: impl FnMut(&'e Expr)) {
+        let mut seen = BTreeSet::new();
+        let mut pending = vec![self];
+        while let Some(expr) = pending.pop() {
+            if !seen.insert(Rc::as_ptr(&expr.0)) {
+                continue;
+            }
+            f(expr);
+            // Reversed so that subterms are visited left to right
+            pending.extend(expr.args().iter().rev());
+        }
+    }
+
+    // Replaces every occurrence of a key of `substitutions` by its value. Replacements are not
+    // themselves substituted into.
+    pub fn substitute(&self, substitutions: &HashMap<Expr, Expr>) -> Self {
+        self.substitute_cached(substitutions, &mut HashMap::new())
+    }
+
+    fn substitute_cached(
+        &self,
+        substitutions: &HashMap<Expr, Expr>,
+        done: &mut HashMap<*const Node, Expr>,
+    ) -> Self {
+        if let Some(replacement) = substitutions.get(self) {
+            return replacement.clone();
+        }
+        if self.args().is_empty() {
+            return self.clone();
+        }
+        if let Some(replaced) = done.get(&Rc::as_ptr(&self.0)) {
+            return replaced.clone();
+        }
+        let args = self
+            .args()
+            .iter()
+            .map(|arg| arg.substitute_cached(substitutions, done))
+            .collect::<Vec<_>>();
+        let replaced = if args == self.args() {
+            self.clone()
+        } else {
+            Self::apply(self.op().clone(), args)
+        };
+        done.insert(Rc::as_ptr(&self.0), replaced.clone());
+        replaced
+    }
+}
+
+fn result_sort(op: &Op, args: &[Expr]) -> ExprResult<Sort> {
+    let sorts = args.iter().map(Expr::sort).collect::<Vec<_>>();
+    let ill_sorted = || ExprError::IllSorted {
+        op: format!("{op:?}"),
+        args: sorts.iter().map(|&sort| sort.clone()).collect(),
+    };
+    if let Op::ConstArray(index) = op {
+        index.check()?;
+    }
+    if let Op::IntToBv(0) = op {
+        return Err(ExprError::ZeroWidth);
+    }
+    let sort = match (op, &sorts[..]) {
+        (Op::ConstArray(index), [value]) => Some(Sort::array(index.clone(), (*value).clone())),
+        (Op::Not, [Sort::Bool]) => Some(Sort::Bool),
+        (Op::And | Op::Or | Op::Xor | Op::Implies, [Sort::Bool, Sort::Bool]) => Some(Sort::Bool),
+        (Op::Eq, [a, b]) if a == b => Some(Sort::Bool),
+        (Op::Ite, [Sort::Bool, a, b]) if a == b => Some((*a).clone()),
+        (
+            Op::Add | Op::Sub | Op::Mul | Op::Div | Op::Rem,
+            [a @ (Sort::Int | Sort::BitVec(_)), b],
+        ) if a == b => Some((*a).clone()),
+        (Op::Neg, [a @ (Sort::Int | Sort::BitVec(_))]) => Some((*a).clone()),
+        (Op::Lt | Op::Le | Op::Gt | Op::Ge, [a @ (Sort::Int | Sort::BitVec(_)), b]) if a == b => {
+            Some(Sort::Bool)
+        }
+        (Op::Slt | Op::Sle | Op::Sgt | Op::Sge, [Sort::BitVec(a), Sort::BitVec(b)]) if a == b => {
+            Some(Sort::Bool)
+        }
+        (Op::BvNot, [a @ Sort::BitVec(_)]) => Some((*a).clone()),
+        (
+            Op::Sdiv
+            | Op::Srem
+            | Op::Smod
+            | Op::BvAnd
+            | Op::BvOr
+            | Op::BvXor
+            | Op::Shl
+            | Op::Lshr
+            | Op::Ashr,
+            [a @ Sort::BitVec(_), b],
+        ) if a == b => Some((*a).clone()),
+        (Op::Concat, [Sort::BitVec(a), Sort::BitVec(b)]) => Some(Sort::BitVec(a + b)),
+        (Op::Extract(high, low), [Sort::BitVec(width)]) => {
+            if low > high || high >= width {
+                return Err(ExprError::ExtractOutOfRange {
+                    high: *high,
+                    low: *low,
+                    width: *width,
+                });
+            }
+            Some(Sort::BitVec(high - low + 1))
+        }
+        (Op::ZeroExt(bits) | Op::SignExt(bits), [Sort::BitVec(width)]) => {
+            Some(Sort::BitVec(width + bits))
+        }
+        (Op::BvToInt, [Sort::BitVec(_)]) => Some(Sort::Int),
+        (Op::IntToBv(width), [Sort::Int]) => Some(Sort::BitVec(*width)),
+        (Op::Select, [Sort::Array(index, value), i]) if **index == **i => Some((**value).clone()),
+        (Op::Store, [array @ Sort::Array(index, value), i, v])
+            if **index == **i && **value == **v =>
+        {
+            Some((*array).clone())
+        }
+        (Op::Apply(_, range), [_, ..]) => {
+            range.check()?;
+            Some(range.clone())
+        }
+        _ => None,
+    };
+    sort.ok_or_else(ill_sorted)
+}
+
+impl Add for Expr {
+    type Output = Expr;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        Expr::add(&self, &rhs)
+    }
+}
+
+impl Sub for Expr {
+    type Output = Expr;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        Expr::sub(&self, &rhs)
+    }
+}
+
+impl Mul for Expr {
+    type Output = Expr;
+
+    fn mul(self, rhs: Self) -> Self::Output {
+        Expr::mul(&self, &rhs)
+    }
+}
+
+impl Neg for Expr {
+    type Output = Expr;
+
+    fn neg(self) -> Self::Output {
+        Expr::neg(&self)
+    }
+}
+
+// Logical negation of a Bool, bitwise negation of a bitvector
+impl Not for Expr {
+    type Output = Expr;
+
+    fn not(self) -> Self::Output {
+        match self.sort() {
+            Sort::BitVec(_) => self.bvnot(),
+            _ => Expr::not(&self),
+        }
+    }
+}
@@ -0,0 +1,138 @@
+use std::collections::{HashMap, HashSet};
+
+use super::{Expr, Op, Sort};
+
+// Values of the symbolic inputs of some constraints, as reported by a solver. Anything the
+// solver was not asked about evaluates to a default: false, zero, or an array of zeros.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Model {
+    consts: HashMap<String, Expr>,
+    // Known (index, value) entries of array variables
+    arrays: HashMap<String, Vec<(Expr, Expr)>>,
+    // Known (arguments, value) entries of uninterpreted functions
+    functions: HashMap<String, Vec<(Vec<Expr>, Expr)>>,
+}
+
+impl Model {
+    // The terms a solver must report values for to build a model of `constraints`: the
+    // scalar variables, every index an array variable is read at along with the value read,
+    // and the arguments and value of every function application
+    pub fn queries(constraints: &[Expr]) -> Vec<Expr> {
+        let mut queries = vec![];
+        let mut seen = HashSet::new();
+        let mut query = |term: Expr| {
+            if seen.insert(term.clone()) {
+                queries.push(term);
+            }
+        };
+        for constraint in constraints {
+            constraint.visit(|expr| match (expr.op(), expr.args()) {
+                (Op::Var(_), _) if !matches!(expr.sort(), Sort::Array(..)) => query(expr.clone()),
+                (Op::Select, [array, index]) => {
+                    if let Some(base) = base_array(array) {
+                        query(index.clone());
+                        query(base.select(index));
+                    }
+                }
+                (Op::Apply(..), args) => {
+                    args.iter().cloned().for_each(&mut query);
+                    query(expr.clone());
+                }
+                _ => {}
+            });
+        }
+        queries
+    }
+
+    // Builds a model from the values of `queries`, as returned by `Model::queries`, in order
+    pub fn from_values(queries: &[Expr], values: &[Expr]) -> Self {
+        let known = queries.iter().zip(values).collect::<HashMap<_, _>>();
+        let value_of = |term: &Expr| known.get(term).map(|&value| value.clone());
+        let mut model = Self::default();
+        for (query, value) in queries.iter().zip(values) {
+            match (query.op(), query.args()) {
+                (Op::Var(name), _) => {
+                    model.consts.insert(name.clone(), value.clone());
+                }
+                (Op::Select, [array, index]) => {
+                    let (Op::Var(name), Some(index)) = (array.op(), value_of(index)) else {
+                        continue;
+                    };
+                    let entries = model.arrays.entry(name.clone()).or_default();
+                    entries.push((index, value.clone()));
+                }
+                (Op::Apply(name, _), args) => {
+                    let Some(args) = args.iter().map(value_of).collect::<Option<Vec<_>>>() else {
+                        continue;
+                    };
+                    let entries = model.functions.entry(name.clone()).or_default();
+                    entries.push((args, value.clone()));
+                }
+                _ => {}
+            }
+        }
+        model
+    }
+
+    // The value of `expr` under the model: a constant, or for an array a constant array with
+    // the known entries stored into it
+    pub fn eval(&self, expr: &Expr) -> Expr {
+        self.eval_cached(expr, &mut HashMap::new())
+    }
+
+    fn eval_cached(&self, expr: &Expr, done: &mut HashMap<Expr, Expr>) -> Expr {
+        if let Some(value) = done.get(expr) {
+            return value.clone();
+        }
+        let args = expr
+            .args()
+            .iter()
+            .map(|arg| self.eval_cached(arg, done))
+            .collect::<Vec<_>>();
+        let value = match expr.op() {
+            Op::Var(name) => match expr.sort() {
+                Sort::Array(index, value) => {
+                    let entries = self.arrays.get(name).into_iter().flatten();
+                    entries.fold(
+                        Expr::const_array((**index).clone(), &default(value)),
+                        |array, (index, value)| array.store(index, value),
+                    )
+                }
+                sort => self
+                    .consts
+                    .get(name)
+                    .cloned()
+                    .unwrap_or_else(|| default(sort)),
+            },
+            Op::Apply(name, range) => {
+                let mut entries = self.functions.get(name).into_iter().flatten();
+                entries
+                    .find(|(known, _)| *known == args)
+                    .map(|(_, value)| value.clone())
+                    .unwrap_or_else(|| default(range))
+            }
+            _ if args.is_empty() => expr.clone(),
+            op => Expr::apply(op.clone(), args).simplify(),
+        };
+        done.insert(expr.clone(), value.clone());
+        value
+    }
+}
+
+// The array variable an array term stores into, if any
+fn base_array(array: &Expr) -> Option<&Expr> {
+    match array.op() {
+        Op::Var(_) => Some(array),
+        Op::Store => base_array(&array.args()[0]),
+        _ => None,
+    }
+}
+
+fn default(sort: &Sort) -> Expr {
+    match sort {
+        Sort::Bool => Expr::bool(false),
+        Sort::Int => Expr::int(0),
+        Sort::BitVec(width) => Expr::bv(0, *width),
+        Sort::Array(index, value) => Expr::const_array((**index).clone(), &default(value)),
+    }
+}
@@ -0,0 +1,450 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use super::{Expr, Node, Op, Sort};
+
+impl Expr {
+    // Folds constant subterms and applies local identities such as `x + 0 = x` bottom up.
+    // Int arithmetic that would overflow an i128 is left alone.
+    pub fn simplify(&self) -> Self {
+        self.simplify_cached(&mut HashMap::new())
+    }
+
+    fn simplify_cached(&self, done: &mut HashMap<*const Node, Expr>) -> Self {
+        if self.args().is_empty() {
+            return self.clone();
+        }
+        if let Some(simplified) = done.get(&Rc::as_ptr(&self.0)) {
+            return simplified.clone();
+        }
+        let args = self
+            .args()
+            .iter()
+            .map(|arg| arg.simplify_cached(done))
+            .collect::<Vec<_>>();
+        let simplified = fold(self.op(), &args, self.sort()).unwrap_or_else(|| {
+            if args == self.args() {
+                self.clone()
+            } else {
+                Self::apply(self.op().clone(), args)
+            }
+        });
+        done.insert(Rc::as_ptr(&self.0), simplified.clone());
+        simplified
+    }
+
+    pub(crate) fn is_constant(&self) -> bool {
+        matches!(self.op(), Op::Bool(_) | Op::Int(_) | Op::BitVec(_))
+    }
+
+    fn is_zero(&self) -> bool {
+        match self.op() {
+            Op::Int(value) => *value == 0,
+            Op::BitVec(limbs) => limbs.iter().all(|&limb| limb == 0),
+            _ => false,
+        }
+    }
+
+    fn is_one(&self) -> bool {
+        match self.op() {
+            Op::Int(value) => *value == 1,
+            Op::BitVec(limbs) => limbs[0] == 1 && limbs[1..].iter().all(|&limb| limb == 0),
+            _ => false,
+        }
+    }
+}
+
+// The simplified form of `op` applied to already simplified `args`, if it differs
+fn fold(op: &Op, args: &[Expr], sort: &Sort) -> Option<Expr> {
+    if let Some(values) = args.iter().map(Expr::as_bool).collect::<Option<Vec<_>>>() {
+        if let Some(folded) = fold_bools(op, &values) {
+            return Some(folded);
+        }
+    }
+    if let Some(values) = args.iter().map(Expr::as_int).collect::<Option<Vec<_>>>() {
+        if let Some(folded) = fold_ints(op, &values) {
+            return Some(folded);
+        }
+    }
+    if let Some(values) = args.iter().map(Bits::of).collect::<Option<Vec<_>>>() {
+        if let Some(folded) = fold_bits(op, &values) {
+            return Some(folded);
+        }
+    }
+    fold_identities(op, args, sort)
+}
+
+fn fold_bools(op: &Op, args: &[bool]) -> Option<Expr> {
+    let value = match (op, args) {
+        (Op::Not, [a]) => !a,
+        (Op::And, [a, b]) => *a && *b,
+        (Op::Or, [a, b]) => *a || *b,
+        (Op::Xor, [a, b]) => a != b,
+        (Op::Implies, [a, b]) => !a || *b,
+        (Op::Eq, [a, b]) => a == b,
+        _ => return None,
+    };
+    Some(Expr::bool(value))
+}
+
+fn fold_ints(op: &Op, args: &[i128]) -> Option<Expr> {
+    let &[a, ref rest @ ..] = args else {
+        return None;
+    };
+    let b = rest.first().copied();
+    match (op, b) {
+        (Op::Add, Some(b)) => a.checked_add(b).map(Expr::int),
+        (Op::Sub, Some(b)) => a.checked_sub(b).map(Expr::int),
+        (Op::Mul, Some(b)) => a.checked_mul(b).map(Expr::int),
+        // Division by zero is left to the solver, where it is unspecified
+        (Op::Div, Some(b)) if b != 0 => a.checked_div_euclid(b).map(Expr::int),
+        (Op::Rem, Some(b)) if b != 0 => a.checked_rem_euclid(b).map(Expr::int),
+        (Op::Neg, None) => a.checked_neg().map(Expr::int),
+        (Op::Eq, Some(b)) => Some(Expr::bool(a == b)),
+        (Op::Lt, Some(b)) => Some(Expr::bool(a < b)),
+        (Op::Le, Some(b)) => Some(Expr::bool(a <= b)),
+        (Op::Gt, Some(b)) => Some(Expr::bool(a > b)),
+        (Op::Ge, Some(b)) => Some(Expr::bool(a >= b)),
+        (Op::IntToBv(width), None) => Some(Bits::from_i128(a, *width).into()),
+        _ => None,
+    }
+}
+
+fn fold_bits(op: &Op, args: &[Bits]) -> Option<Expr> {
+    let folded = match (op, args) {
+        (Op::Add, [a, b]) => a.add(b).into(),
+        (Op::Sub, [a, b]) => a.add(&b.neg()).into(),
+        (Op::Mul, [a, b]) => a.mul(b).into(),
+        // SMT-LIB defines x / 0 as all ones and x % 0 as x
+        (Op::Div, [a, b]) => a.udivrem(b).0.into(),
+        (Op::Rem, [a, b]) => a.udivrem(b).1.into(),
+        (Op::Sdiv, [a, b]) => a.sdiv(b).into(),
+        (Op::Srem, [a, b]) => a.srem(b).into(),
+        (Op::Smod, [a, b]) => a.smod(b).into(),
+        (Op::Neg, [a]) => a.neg().into(),
+        (Op::Eq, [a, b]) => Expr::bool(a == b),
+        (Op::Lt, [a, b]) => Expr::bool(a.ucmp(b).is_lt()),
+        (Op::Le, [a, b]) => Expr::bool(a.ucmp(b).is_le()),
+        (Op::Gt, [a, b]) => Expr::bool(a.ucmp(b).is_gt()),
+        (Op::Ge, [a, b]) => Expr::bool(a.ucmp(b).is_ge()),
+        (Op::Slt, [a, b]) => Expr::bool(a.scmp(b).is_lt()),
+        (Op::Sle, [a, b]) => Expr::bool(a.scmp(b).is_le()),
+        (Op::Sgt, [a, b]) => Expr::bool(a.scmp(b).is_gt()),
+        (Op::Sge, [a, b]) => Expr::bool(a.scmp(b).is_ge()),
+        (Op::BvNot, [a]) => a.map(|a| !a).into(),
+        (Op::BvAnd, [a, b]) => a.zip(b, |a, b| a & b).into(),
+        (Op::BvOr, [a, b]) => a.zip(b, |a, b| a | b).into(),
+        (Op::BvXor, [a, b]) => a.zip(b, |a, b| a ^ b).into(),
+        (Op::Shl, [a, b]) => a.shl(b.shift(a.width)).into(),
+        (Op::Lshr, [a, b]) => a.lshr(b.shift(a.width), false).into(),
+        (Op::Ashr, [a, b]) => a.lshr(b.shift(a.width), a.negative()).into(),
+        (Op::Concat, [a, b]) => {
+            let high = a.resize(a.width + b.width, false).shl(b.width);
+            high.zip(&b.resize(high.width, false), |a, b| a | b).into()
+        }
+        (Op::Extract(high, low), [a]) => a.lshr(*low, false).resize(high - low + 1, false).into(),
+        (Op::ZeroExt(bits), [a]) => a.resize(a.width + bits, false).into(),
+        (Op::SignExt(bits), [a]) => a.resize(a.width + bits, a.negative()).into(),
+        (Op::BvToInt, [a]) => Expr::int(a.to_i128()?),
+        _ => return None,
+    };
+    Some(folded)
+}
+
+// A bitvector constant: little-endian limbs with the bits past `width` clear
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Bits {
+    limbs: Vec<u64>,
+    width: u32,
+}
+
+impl Bits {
+    fn of(expr: &Expr) -> Option<Self> {
+        match (expr.op(), expr.sort()) {
+            (Op::BitVec(limbs), Sort::BitVec(width)) => Some(Self {
+                limbs: limbs.clone(),
+                width: *width,
+            }),
+            _ => None,
+        }
+    }
+
+    // `limbs` are extended or truncated to the width, and the excess bits cleared
+    fn new(mut limbs: Vec<u64>, width: u32) -> Self {
+        let len = width.div_ceil(64) as usize;
+        limbs.resize(len, 0);
+        if !width.is_multiple_of(64) {
+            limbs[len - 1] &= (1 << (width % 64)) - 1;
+        }
+        Self { limbs, width }
+    }
+
+    fn zero(width: u32) -> Self {
+        Self::new(vec![], width)
+    }
+
+    // Two's complement of `value` in `width` bits
+    fn from_i128(value: i128, width: u32) -> Self {
+        let fill = if value < 0 { u64::MAX } else { 0 };
+        let mut limbs = vec![fill; width.div_ceil(64) as usize];
+        for (i, limb) in limbs.iter_mut().take(2).enumerate() {
+            *limb = (value >> (64 * i)) as u64;
+        }
+        Self::new(limbs, width)
+    }
+
+    fn bit(&self, i: u32) -> bool {
+        (self.limbs[(i / 64) as usize] >> (i % 64)) & 1 == 1
+    }
+
+    fn negative(&self) -> bool {
+        self.bit(self.width - 1)
+    }
+
+    fn is_zero(&self) -> bool {
+        self.limbs.iter().all(|&limb| limb == 0)
+    }
+
+    fn to_i128(&self) -> Option<i128> {
+        if self.limbs[2.min(self.limbs.len())..]
+            .iter()
+            .any(|&limb| limb != 0)
+        {
+            return None;
+        }
+        let low = self.limbs[0] as u128;
+        let high = self.limbs.get(1).copied().unwrap_or(0) as u128;
+        i128::try_from(high << 64 | low).ok()
+    }
+
+    // The shift amount this value denotes, capped at `width`
+    fn shift(&self, width: u32) -> u32 {
+        let (first, rest) = self.limbs.split_first().expect("at least one limb");
+        if rest.iter().any(|&limb| limb != 0) {
+            width
+        } else {
+            (*first).min(width as u64) as u32
+        }
+    }
+
+    fn map(&self, f: impl Fn(u64) -> u64) -> Self {
+        Self::new(self.limbs.iter().map(|&a| f(a)).collect(), self.width)
+    }
+
+    fn zip(&self, other: &Self, f: impl Fn(u64, u64) -> u64) -> Self {
+        let limbs = self.limbs.iter().zip(&other.limbs);
+        Self::new(limbs.map(|(&a, &b)| f(a, b)).collect(), self.width)
+    }
+
+    fn add(&self, other: &Self) -> Self {
+        let mut carry = false;
+        let limbs = self.limbs.iter().zip(&other.limbs).map(|(&a, &b)| {
+            let (sum, c1) = a.overflowing_add(b);
+            let (sum, c2) = sum.overflowing_add(u64::from(carry));
+            carry = c1 || c2;
+            sum
+        });
+        Self::new(limbs.collect(), self.width)
+    }
+
+    fn neg(&self) -> Self {
+        self.map(|a| !a).add(&Self::new(vec![1], self.width))
+    }
+
+    fn mul(&self, other: &Self) -> Self {
+        let len = self.limbs.len();
+        let mut limbs = vec![0u64; len];
+        for (i, &a) in self.limbs.iter().enumerate() {
+            let mut carry = 0u128;
+            for (j, &b) in other.limbs[..len - i].iter().enumerate() {
+                let product = a as u128 * b as u128 + limbs[i + j] as u128 + carry;
+                limbs[i + j] = product as u64;
+                carry = product >> 64;
+            }
+        }
+        Self::new(limbs, self.width)
+    }
+
+    // Quotient and remainder, bit by bit. Dividing by zero gives all ones and `self`.
+    fn udivrem(&self, other: &Self) -> (Self, Self) {
+        if other.is_zero() {
+            return (Self::zero(self.width).map(|_| u64::MAX), self.clone());
+        }
+        let mut quotient = Self::zero(self.width);
+        let mut remainder = Self::zero(self.width);
+        for i in (0..self.width).rev() {
+            // The remainder stays below `other`, so one more bit cannot overflow it unless
+            // its top bit is set
+            let carry = remainder.negative();
+            remainder = remainder.shl(1);
+            if self.bit(i) {
+                remainder.limbs[0] |= 1;
+            }
+            if carry || remainder.ucmp(other).is_ge() {
+                remainder = remainder.add(&other.neg());
+                quotient.limbs[(i / 64) as usize] |= 1 << (i % 64);
+            }
+        }
+        (quotient, remainder)
+    }
+
+    // Signed division works on magnitudes and fixes up the signs, as SMT-LIB defines it
+    fn abs(&self) -> Self {
+        if self.negative() {
+            self.neg()
+        } else {
+            self.clone()
+        }
+    }
+
+    fn sdiv(&self, other: &Self) -> Self {
+        let quotient = self.abs().udivrem(&other.abs()).0;
+        if self.negative() != other.negative() {
+            quotient.neg()
+        } else {
+            quotient
+        }
+    }
+
+    fn srem(&self, other: &Self) -> Self {
+        let remainder = self.abs().udivrem(&other.abs()).1;
+        if self.negative() {
+            remainder.neg()
+        } else {
+            remainder
+        }
+    }
+
+    fn smod(&self, other: &Self) -> Self {
+        let remainder = self.abs().udivrem(&other.abs()).1;
+        match (self.negative(), other.negative()) {
+            _ if remainder.is_zero() => remainder,
+            (false, false) => remainder,
+            (true, false) => remainder.neg().add(other),
+            (false, true) => remainder.add(other),
+            (true, true) => remainder.neg(),
+        }
+    }
+
+    fn ucmp(&self, other: &Self) -> Ordering {
+        self.limbs.iter().rev().cmp(other.limbs.iter().rev())
+    }
+
+    fn scmp(&self, other: &Self) -> Ordering {
+        match (self.negative(), other.negative()) {
+            (true, false) => Ordering::Less,
+            (false, true) => Ordering::Greater,
+            _ => self.ucmp(other),
+        }
+    }
+
+    fn shl(&self, bits: u32) -> Self {
+        if bits >= self.width {
+            return Self::zero(self.width);
+        }
+        let (limbs, bits) = ((bits / 64) as usize, bits % 64);
+        let mut shifted = vec![0u64; self.limbs.len()];
+        for (i, limb) in shifted.iter_mut().enumerate().skip(limbs) {
+            *limb = self.limbs[i - limbs] << bits;
+            if bits > 0 && i > limbs {
+                *limb |= self.limbs[i - limbs - 1] >> (64 - bits);
+            }
+        }
+        Self::new(shifted, self.width)
+    }
+
+    // Shifts in ones instead of zeros when `fill` is set
+    fn lshr(&self, bits: u32, fill: bool) -> Self {
+        let filled = |value: Self| if fill { value.map(|a| !a) } else { value };
+        let value = filled(self.clone());
+        if bits >= self.width {
+            return filled(Self::zero(self.width));
+        }
+        let (limbs, bits) = ((bits / 64) as usize, bits % 64);
+        let len = value.limbs.len();
+        let mut shifted = vec![0u64; len];
+        for (i, limb) in shifted.iter_mut().take(len - limbs).enumerate() {
+            *limb = value.limbs[i + limbs] >> bits;
+            if bits > 0 && i + limbs + 1 < len {
+                *limb |= value.limbs[i + limbs + 1] << (64 - bits);
+            }
+        }
+        filled(Self::new(shifted, self.width))
+    }
+
+    // Zero or sign extends, or truncates, to `width`
+    fn resize(&self, width: u32, sign: bool) -> Self {
+        let extended = Self::new(self.limbs.clone(), width.max(self.width));
+        let extended = if sign && width > self.width {
+            let ones = Self::zero(width).map(|_| u64::MAX).shl(self.width);
+            extended.zip(&ones, |a, b| a | b)
+        } else {
+            extended
+        };
+        Self::new(extended.limbs, width)
+    }
+}
+
+impl From<Bits> for Expr {
+    fn from(bits: Bits) -> Self {
+        Expr::bv_from_limbs(&bits.limbs, bits.width)
+    }
+}
+
+fn zero(sort: &Sort) -> Expr {
+    match sort {
+        Sort::BitVec(width) => Expr::bv(0, *width),
+        _ => Expr::int(0),
+    }
+}
+
+// Rewrites that hold whatever the values of the non-constant arguments
+fn fold_identities(op: &Op, args: &[Expr], sort: &Sort) -> Option<Expr> {
+    let folded = match (op, args) {
+        (Op::Not, [a]) if *a.op() == Op::Not => a.args()[0].clone(),
+        (Op::And, [a, b]) | (Op::And, [b, a]) if a.as_bool() == Some(false) => a.clone(),
+        (Op::And, [a, b]) | (Op::And, [b, a]) if a.as_bool() == Some(true) => b.clone(),
+        (Op::Or, [a, b]) | (Op::Or, [b, a]) if a.as_bool() == Some(true) => a.clone(),
+        (Op::Or, [a, b]) | (Op::Or, [b, a]) if a.as_bool() == Some(false) => b.clone(),
+        (Op::And | Op::Or, [a, b]) if a == b => a.clone(),
+        (Op::Xor, [a, b]) | (Op::Xor, [b, a]) if a.as_bool() == Some(false) => b.clone(),
+        (Op::Xor, [a, b]) if a == b => Expr::bool(false),
+        (Op::Implies, [a, b]) if a.as_bool() == Some(false) || b.as_bool() == Some(true) => {
+            Expr::bool(true)
+        }
+        (Op::Implies, [a, b]) if a.as_bool() == Some(true) => b.clone(),
+        (Op::Implies, [a, b]) if a == b => Expr::bool(true),
+        (Op::Eq, [a, b]) if a == b => Expr::bool(true),
+        // Distinct constants of one sort have distinct values
+        (Op::Eq, [a, b]) if a.is_constant() && b.is_constant() => Expr::bool(false),
+        (Op::Ite, [condition, then, otherwise]) => match condition.as_bool() {
+            Some(true) => then.clone(),
+            Some(false) => otherwise.clone(),
+            None if then == otherwise => then.clone(),
+            None => return None,
+        },
+        (Op::Add, [a, b]) | (Op::Add, [b, a]) if a.is_zero() => b.clone(),
+        (Op::Sub, [a, b]) if b.is_zero() => a.clone(),
+        (Op::Sub, [a, b]) if a == b => zero(sort),
+        (Op::Mul, [a, _]) | (Op::Mul, [_, a]) if a.is_zero() => a.clone(),
+        (Op::Mul, [a, b]) | (Op::Mul, [b, a]) if a.is_one() => b.clone(),
+        (Op::Div, [a, b]) if b.is_one() => a.clone(),
+        (Op::Neg | Op::BvNot, [a]) if a.op() == op => a.args()[0].clone(),
+        (Op::Select, [array, index]) => return fold_select(array, index),
+        _ => return None,
+    };
+    Some(folded)
+}
+
+// Reads through stores to a known index and constant arrays
+fn fold_select(array: &Expr, index: &Expr) -> Option<Expr> {
+    match (array.op(), array.args()) {
+        (Op::ConstArray(_), [value]) => Some(value.clone()),
+        (Op::Store, [_, stored, value]) if stored == index => Some(value.clone()),
+        (Op::Store, [inner, stored, _]) if stored.is_constant() && index.is_constant() => {
+            Some(fold_select(inner, index).unwrap_or_else(|| inner.select(index)))
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,290 @@
+use std::collections::BTreeSet;
+use std::fmt;
+
+use super::{Expr, Op, Sort};
+
+// A standalone SMT-LIB2 script declaring every variable in `constraints` and checking their
+// conjunction
+pub fn script(constraints: &[Expr]) -> String {
+    let mut script = declarations(constraints);
+    for constraint in constraints {
+        script.push_str(&format!("(assert {constraint})\n"));
+    }
+    script.push_str("(check-sat)\n");
+    script
+}
+
+// A `declare-fun` line for every variable and uninterpreted function in `exprs`
+pub fn declarations(exprs: &[Expr]) -> String {
+    let mut declarations = String::new();
+    let vars = exprs.iter().flat_map(Expr::vars).collect::<BTreeSet<_>>();
+    for (name, sort) in vars {
+        declarations.push_str(&format!("(declare-fun {} () {sort})\n", symbol(&name)));
+    }
+    let functions = exprs
+        .iter()
+        .flat_map(Expr::functions)
+        .collect::<BTreeSet<_>>();
+    for (name, domain, range) in functions {
+        let domain = domain.iter().map(Sort::to_string).collect::<Vec<_>>();
+        declarations.push_str(&format!(
+            "(declare-fun {} ({}) {range})\n",
+            symbol(&name),
+            domain.join(" ")
+        ));
+    }
+    declarations
+}
+
+// Quotes names that are not simple SMT-LIB2 symbols
+fn symbol(name: &str) -> String {
+    let simple = !name.is_empty()
+        && !name.starts_with(|c: char| c.is_ascii_digit())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "~!@$%^&*_-+=<>.?/".contains(c));
+    if simple {
+        name.to_string()
+    } else {
+        format!("|{name}|")
+    }
+}
+
+impl fmt::Display for Sort {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Sort::Bool => write!(f, "Bool"),
+            Sort::Int => write!(f, "Int"),
+            Sort::BitVec(width) => write!(f, "(_ BitVec {width})"),
+            Sort::Array(index, value) => write!(f, "(Array {index} {value})"),
+        }
+    }
+}
+
+// Prints the expression as an SMT-LIB2 term. Shared subterms are printed in full at every
+// occurrence.
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let bv = matches!(self.args().first().map(Expr::sort), Some(Sort::BitVec(_)));
+        let name = match self.op() {
+            Op::Var(name) => return write!(f, "{}", symbol(name)),
+            Op::Bool(value) => return write!(f, "{value}"),
+            Op::Int(value) if *value < 0 => return write!(f, "(- {})", value.unsigned_abs()),
+            Op::Int(value) => return write!(f, "{value}"),
+            Op::BitVec(limbs) => {
+                let Sort::BitVec(width) = self.sort() else {
+                    unreachable!("bitvector constant of sort {:?}", self.sort())
+                };
+                return write_bits(f, limbs, *width);
+            }
+            Op::ConstArray(_) => format!("(as const {})", self.sort()),
+            Op::Not => "not".to_string(),
+            Op::And => "and".to_string(),
+            Op::Or => "or".to_string(),
+            Op::Xor => "xor".to_string(),
+            Op::Implies => "=>".to_string(),
+            Op::Eq => "=".to_string(),
+            Op::Ite => "ite".to_string(),
+            Op::Add => if bv { "bvadd" } else { "+" }.to_string(),
+            Op::Sub => if bv { "bvsub" } else { "-" }.to_string(),
+            Op::Mul => if bv { "bvmul" } else { "*" }.to_string(),
+            Op::Div => if bv { "bvudiv" } else { "div" }.to_string(),
+            Op::Rem => if bv { "bvurem" } else { "mod" }.to_string(),
+            Op::Sdiv => "bvsdiv".to_string(),
+            Op::Srem => "bvsrem".to_string(),
+            Op::Smod => "bvsmod".to_string(),
+            Op::Neg => if bv { "bvneg" } else { "-" }.to_string(),
+            Op::Lt => if bv { "bvult" } else { "<" }.to_string(),
+            Op::Le => if bv { "bvule" } else { "<=" }.to_string(),
+            Op::Gt => if bv { "bvugt" } else { ">" }.to_string(),
+            Op::Ge => if bv { "bvuge" } else { ">=" }.to_string(),
+            Op::Slt => "bvslt".to_string(),
+            Op::Sle => "bvsle".to_string(),
+            Op::Sgt => "bvsgt".to_string(),
+            Op::Sge => "bvsge".to_string(),
+            Op::BvNot => "bvnot".to_string(),
+            Op::BvAnd => "bvand".to_string(),
+            Op::BvOr => "bvor".to_string(),
+            Op::BvXor => "bvxor".to_string(),
+            Op::Shl => "bvshl".to_string(),
+            Op::Lshr => "bvlshr".to_string(),
+            Op::Ashr => "bvashr".to_string(),
+            Op::Concat => "concat".to_string(),
+            Op::Extract(high, low) => format!("(_ extract {high} {low})"),
+            Op::ZeroExt(bits) => format!("(_ zero_extend {bits})"),
+            Op::SignExt(bits) => format!("(_ sign_extend {bits})"),
+            Op::BvToInt => "bv2nat".to_string(),
+            Op::IntToBv(width) => format!("(_ int2bv {width})"),
+            Op::Select => "select".to_string(),
+            Op::Store => "store".to_string(),
+            Op::Apply(name, _) => symbol(name),
+        };
+        write!(f, "({name}")?;
+        for arg in self.args() {
+            write!(f, " {arg}")?;
+        }
+        write!(f, ")")
+    }
+}
+
+impl fmt::Debug for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{self}")
+    }
+}
+
+// Hexadecimal when the width allows it, binary otherwise
+fn write_bits(f: &mut fmt::Formatter<'_>, limbs: &[u64], width: u32) -> fmt::Result {
+    let bit = |i: u32| (limbs[(i / 64) as usize] >> (i % 64)) & 1;
+    if width.is_multiple_of(4) {
+        write!(f, "#x")?;
+        for digit in (0..width / 4).rev() {
+            let value = (0..4).fold(0, |acc, i| acc | bit(digit * 4 + i) << i);
+            write!(f, "{value:x}")?;
+        }
+    } else {
+        write!(f, "#b")?;
+        for i in (0..width).rev() {
+            write!(f, "{}", bit(i))?;
+        }
+    }
+    Ok(())
+}
+
+// Parses a Bool, Int or bitvector value of `sort` as solvers print them
+pub(crate) fn value(text: &SExpr, sort: &Sort) -> Option<Expr> {
+    match (sort, text) {
+        (Sort::Bool, SExpr::Atom(b)) => b.parse().ok().map(Expr::bool),
+        (Sort::Int, SExpr::Atom(n)) => n.parse::<i128>().ok().map(Expr::int),
+        (Sort::Int, SExpr::List(parts)) if parts.len() == 2 && parts[0].is_atom("-") => {
+            Some(value(&parts[1], sort)?.neg().simplify())
+        }
+        (Sort::BitVec(width), SExpr::Atom(bits)) => {
+            let (digits, radix) = match bits.split_at_checked(2)? {
+                ("#x", digits) => (digits, 16),
+                ("#b", digits) => (digits, 2),
+                _ => return None,
+            };
+            Some(Expr::bv_from_limbs(&limbs(digits, radix)?, *width))
+        }
+        // (_ bvN width)
+        (Sort::BitVec(width), SExpr::List(parts)) if parts.len() == 3 && parts[0].is_atom("_") => {
+            let SExpr::Atom(n) = &parts[1] else {
+                return None;
+            };
+            Some(Expr::bv_from_limbs(
+                &limbs(n.strip_prefix("bv")?, 10)?,
+                *width,
+            ))
+        }
+        _ => None,
+    }
+}
+
+// Little-endian 64 bit limbs of a numeral
+fn limbs(digits: &str, radix: u32) -> Option<Vec<u64>> {
+    let mut limbs = vec![0u64];
+    for digit in digits.chars() {
+        let mut carry = digit.to_digit(radix)? as u128;
+        for limb in limbs.iter_mut() {
+            let next = *limb as u128 * radix as u128 + carry;
+            *limb = next as u64;
+            carry = next >> 64;
+        }
+        if carry > 0 {
+            limbs.push(carry as u64);
+        }
+    }
+    Some(limbs)
+}
+
+// Just enough of an s-expression reader for solver responses
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum SExpr {
+    Atom(String),
+    List(Vec<SExpr>),
+}
+
+impl SExpr {
+    // None until `text` holds a complete expression
+    pub(crate) fn parse(text: &str) -> Option<SExpr> {
+        let mut stack: Vec<Vec<SExpr>> = vec![];
+        let mut chars = text.trim_start().chars().peekable();
+        loop {
+            let c = *chars.peek()?;
+            let atom = match c {
+                '(' => {
+                    chars.next();
+                    stack.push(vec![]);
+                    continue;
+                }
+                ')' => {
+                    chars.next();
+                    SExpr::List(stack.pop()?)
+                }
+                c if c.is_whitespace() => {
+                    chars.next();
+                    continue;
+                }
+                // Quoted symbols and string literals keep their delimiters
+                '|' | '"' => {
+                    let mut token = String::from(chars.next()?);
+                    loop {
+                        let next = chars.next()?;
+                        token.push(next);
+                        if next == c {
+                            // A doubled quote escapes itself inside a string literal
+                            if c == '"' && chars.peek() == Some(&'"') {
+                                token.push(chars.next()?);
+                                continue;
+                            }
+                            break;
+                        }
+                    }
+                    SExpr::Atom(token)
+                }
+                _ => {
+                    let mut token = String::new();
+                    while let Some(&next) = chars.peek() {
+                        if next.is_whitespace() || next == '(' || next == ')' {
+                            break;
+                        }
+                        token.push(next);
+                        chars.next();
+                    }
+                    // A bare atom is only complete once something follows it
+                    if stack.is_empty() && chars.peek().is_none() {
+                        return None;
+                    }
+                    SExpr::Atom(token)
+                }
+            };
+            match stack.last_mut() {
+                Some(list) => list.push(atom),
+                None => return Some(atom),
+            }
+        }
+    }
+
+    pub(crate) fn is_atom(&self, text: &str) -> bool {
+        matches!(self, SExpr::Atom(atom) if atom == text)
+    }
+}
+
+impl fmt::Display for SExpr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SExpr::Atom(atom) => write!(f, "{atom}"),
+            SExpr::List(items) => {
+                write!(f, "(")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " ")?;
+                    }
+                    write!(f, "{item}")?;
+                }
+                write!(f, ")")
+            }
+        }
+    }
+}
@@ -1,9 +1,16 @@
 use thiserror::{self, Error};
 
-#[derive(Debug, Error)]
+use crate::expr::Sort;
+use crate::stack::error::StackError;
+
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
 pub enum InstructionError {
     #[error("Unrecognized instruction {0}")]
     UnknownInstruction(String),
     #[error("Failed to execute instruction {0}")]
     InstructionExecutionFailure(String),
+    #[error("Jump destination of sort {0} is not an Int or bitvector")]
+    JumpDestination(Sort),
+    #[error(transparent)]
+    Stack(#[from] StackError),
 }
@@ -1,30 +1,78 @@
 pub mod error;
 pub mod val;
+pub mod word;
+use crate::expr::{Expr, Sort};
 use crate::memory::*;
 use crate::stack::*;
 use error::InstructionError;
-use z3::ast::Bool;
 
 pub type InstructionResult<T> = Result<T, InstructionError>;
-pub struct ExecRecord<'a, S, M>
+
+// Where execution continues after an instruction
+#[derive(Debug, Clone)]
+pub enum JumpTarget {
+    // The following instruction
+    Next,
+    Concrete(usize),
+    // A computed Int destination; the machine forks once per feasible value of it
+    Symbolic(Expr),
+}
+
+// One state an instruction can transition to, taken when all of its constraints hold
+#[derive(Debug, Clone)]
+pub struct Successor {
+    pub target: JumpTarget,
+    pub constraints: Vec<Expr>,
+}
+
+impl Successor {
+    pub fn next(constraints: Vec<Expr>) -> Self {
+        Self {
+            target: JumpTarget::Next,
+            constraints,
+        }
+    }
+
+    pub fn jump(pc: usize, constraints: Vec<Expr>) -> Self {
+        Self {
+            target: JumpTarget::Concrete(pc),
+            constraints,
+        }
+    }
+
+    // `dest` is an Int, or a bitvector read as unsigned
+    pub fn symbolic_jump(dest: Expr, constraints: Vec<Expr>) -> InstructionResult<Self> {
+        let dest = match dest.sort() {
+            Sort::Int => dest,
+            Sort::BitVec(_) => dest.bv_to_int(),
+            sort => return Err(InstructionError::JumpDestination(sort.clone())),
+        };
+        Ok(Self {
+            target: JumpTarget::Symbolic(dest),
+            constraints,
+        })
+    }
+}
+
+pub struct ExecRecord<S, M>
 where
     M: WriteableMem,
     S: Stack,
 {
     pub stack_diff: Option<StackRecord<S>>,
     pub mem_diff: Option<MemRecord<M>>,
-    // Each inner vec represents a new path in the program
-    pub path_constraints: Vec<Vec<Bool<'a>>>,
-    pub pc_change: Option<usize>,
+    // Each successor represents a new path in the program. No successors means falling
+    // through to the next instruction unconditionally.
+    pub successors: Vec<Successor>,
     pub halt: bool,
 }
 
-pub trait VMInstruction<'a> {
+pub trait VMInstruction {
     type ValStack: Stack;
     type Mem: RWMem;
     fn exec(
         &self,
         stack: &Self::ValStack,
         memory: &Self::Mem,
-    ) -> InstructionResult<ExecRecord<'a, Self::ValStack, Self::Mem>>;
+    ) -> InstructionResult<ExecRecord<Self::ValStack, Self::Mem>>;
 }
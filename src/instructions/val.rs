@@ -1,10 +1,39 @@
-use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
-use hex::{decode, encode};
 use std::borrow::Borrow;
-use std::ops::{Add, Sub};
-use z3::ast::{Ast, Int, BV};
-use z3::Context;
-#[derive(Debug, Clone)]
+use std::ops::{Add, BitAnd, BitOr, BitXor, Div, Mul, Not, Rem, Shl, Shr, Sub};
+
+use crate::expr::{Expr, Sort};
+use crate::stack::ConcreteEq;
+
+// Values the machine folds before pushing them, so that concrete computations stay concrete
+pub trait ConstantFold {
+    fn fold_constants(&self) -> Self;
+}
+
+impl ConstantFold for Expr {
+    fn fold_constants(&self) -> Self {
+        self.simplify()
+    }
+}
+
+impl<T: ConstantFold> ConstantFold for Val<T> {
+    fn fold_constants(&self) -> Self {
+        Self(self.0.fold_constants())
+    }
+}
+
+// Both sides are folded, so `1 + 2` equals `3`
+impl ConcreteEq for Expr {
+    fn concrete_eq(&self, other: &Self) -> Option<bool> {
+        self._eq(other).simplify().as_bool()
+    }
+}
+
+impl<T: ConcreteEq> ConcreteEq for Val<T> {
+    fn concrete_eq(&self, other: &Self) -> Option<bool> {
+        self.0.concrete_eq(&other.0)
+    }
+}
+#[derive(Debug, Clone, PartialEq)]
 pub struct Val<T>(pub T);
 
 impl<T> From<T> for Val<T> {
@@ -25,93 +54,409 @@ impl<T> Borrow<T> for Val<T> {
     }
 }
 
-impl<T> Add for Val<T>
+// Operators on `Val<T>` forward to `T`
+macro_rules! forward_binop {
+    ($($trait:ident $method:ident),*) => {
+        $(
+            impl<T> $trait for Val<T>
+            where
+                T: $trait<Output = T>,
+            {
+                type Output = Self;
+
+                fn $method(self, rhs: Self) -> Self::Output {
+                    Self(self.0.$method(rhs.0))
+                }
+            }
+        )*
+    };
+}
+
+forward_binop!(
+    Add add,
+    Sub sub,
+    Mul mul,
+    Div div,
+    Rem rem,
+    BitAnd bitand,
+    BitOr bitor,
+    BitXor bitxor,
+    Shl shl,
+    Shr shr
+);
+
+impl<T> Not for Val<T>
 where
-    T: Add<Output = T>,
+    T: Not<Output = T>,
 {
     type Output = Self;
 
+    fn not(self) -> Self::Output {
+        Self(!self.0)
+    }
+}
+
+// A value that stays concrete until it meets a symbolic one. Mixed operations lift the
+// concrete operand to an Int. Arithmetic is on unbounded Ints, and stays concrete while the
+// result fits a u64; results that do not, and division by zero, become Int terms as they
+// would be for symbolic operands. The bitwise operators work on the low 64 bits of both.
+#[derive(Debug, Clone, PartialEq)]
+pub enum HybridInner {
+    C(u64),
+    S(IntTerm),
+}
+
+// An Int term. Only `HybridInner::symbolic` and the operations here build one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IntTerm(Expr);
+
+impl IntTerm {
+    pub fn as_expr(&self) -> &Expr {
+        &self.0
+    }
+}
+
+// Both operands of a binary operation, after lifting
+enum Operands {
+    Concrete(u64, u64),
+    Symbolic(Expr, Expr),
+}
+
+impl HybridInner {
+    // Panics unless `value` is an Int
+    pub fn symbolic(value: Expr) -> Self {
+        assert_eq!(value.sort(), &Sort::Int, "hybrid values are Ints");
+        Self::S(IntTerm(value))
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            Self::C(value) => Some(*value),
+            Self::S(IntTerm(value)) => value.as_u64(),
+        }
+    }
+
+    pub fn to_expr(&self) -> Expr {
+        match self {
+            Self::C(value) => Expr::int(*value),
+            Self::S(IntTerm(value)) => value.clone(),
+        }
+    }
+
+    pub fn lt(&self, other: &Self) -> HybridBool {
+        self.compare(other, |a, b| a < b, Expr::lt)
+    }
+
+    pub fn le(&self, other: &Self) -> HybridBool {
+        self.compare(other, |a, b| a <= b, Expr::le)
+    }
+
+    pub fn gt(&self, other: &Self) -> HybridBool {
+        self.compare(other, |a, b| a > b, Expr::gt)
+    }
+
+    pub fn ge(&self, other: &Self) -> HybridBool {
+        self.compare(other, |a, b| a >= b, Expr::ge)
+    }
+
+    pub fn _eq(&self, other: &Self) -> HybridBool {
+        self.compare(other, |a, b| a == b, Expr::_eq)
+    }
+
+    fn operands(&self, other: &Self) -> Operands {
+        match (self, other) {
+            (Self::C(a), Self::C(b)) => Operands::Concrete(*a, *b),
+            (a, b) => Operands::Symbolic(a.to_expr(), b.to_expr()),
+        }
+    }
+
+    // `concrete` gives None when the result is not a u64
+    fn arith(
+        &self,
+        other: &Self,
+        concrete: impl FnOnce(u64, u64) -> Option<u64>,
+        symbolic: impl FnOnce(&Expr, &Expr) -> Expr,
+    ) -> Self {
+        match self.operands(other) {
+            Operands::Concrete(a, b) => match concrete(a, b) {
+                Some(value) => Self::C(value),
+                None => Self::S(IntTerm(symbolic(&Expr::int(a), &Expr::int(b)).simplify())),
+            },
+            Operands::Symbolic(a, b) => Self::S(IntTerm(symbolic(&a, &b))),
+        }
+    }
+
+    fn bitwise(
+        &self,
+        other: &Self,
+        concrete: impl FnOnce(u64, u64) -> u64,
+        symbolic: impl FnOnce(&Expr, &Expr) -> Expr,
+    ) -> Self {
+        self.arith(
+            other,
+            |a, b| Some(concrete(a, b)),
+            |a, b| symbolic(&a.int_to_bv(64), &b.int_to_bv(64)).bv_to_int(),
+        )
+    }
+
+    fn compare(
+        &self,
+        other: &Self,
+        concrete: impl FnOnce(u64, u64) -> bool,
+        symbolic: impl FnOnce(&Expr, &Expr) -> Expr,
+    ) -> HybridBool {
+        match self.operands(other) {
+            Operands::Concrete(a, b) => HybridBool::C(concrete(a, b)),
+            Operands::Symbolic(a, b) => HybridBool::S(symbolic(&a, &b)),
+        }
+    }
+}
+
+impl From<u64> for HybridInner {
+    fn from(value: u64) -> Self {
+        Self::C(value)
+    }
+}
+
+impl From<Expr> for HybridInner {
+    fn from(value: Expr) -> Self {
+        Self::symbolic(value)
+    }
+}
+
+impl From<HybridInner> for Expr {
+    fn from(value: HybridInner) -> Self {
+        value.to_expr()
+    }
+}
+
+// Symbolic values that fold to a constant become concrete again
+impl ConstantFold for HybridInner {
+    fn fold_constants(&self) -> Self {
+        match self {
+            Self::C(value) => Self::C(*value),
+            Self::S(IntTerm(value)) => {
+                let value = value.simplify();
+                match value.as_u64() {
+                    Some(value) => Self::C(value),
+                    None => Self::S(IntTerm(value)),
+                }
+            }
+        }
+    }
+}
+
+impl ConcreteEq for HybridInner {
+    fn concrete_eq(&self, other: &Self) -> Option<bool> {
+        match self.operands(other) {
+            Operands::Concrete(a, b) => Some(a == b),
+            Operands::Symbolic(a, b) => a.concrete_eq(&b),
+        }
+    }
+}
+
+impl Add for HybridInner {
+    type Output = Self;
+
     fn add(self, rhs: Self) -> Self::Output {
-        Self(self.0 + rhs.0)
+        self.arith(&rhs, u64::checked_add, Expr::add)
     }
 }
 
-impl<T> Sub for Val<T>
-where
-    T: Sub<Output = T>,
-{
+impl Sub for HybridInner {
     type Output = Self;
 
     fn sub(self, rhs: Self) -> Self::Output {
-        Self(self.0 - rhs.0)
+        self.arith(&rhs, u64::checked_sub, Expr::sub)
     }
 }
 
-pub enum HybridInner<'a> {
-    C(u64),
-    S(Box<Int<'a>>),
+impl Mul for HybridInner {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self::Output {
+        self.arith(&rhs, u64::checked_mul, Expr::mul)
+    }
 }
-impl Add for HybridInner<'_> {
+
+impl Div for HybridInner {
     type Output = Self;
 
-    fn add(self, rhs: Self) -> Self::Output {
+    fn div(self, rhs: Self) -> Self::Output {
+        self.arith(&rhs, u64::checked_div, Expr::div)
+    }
+}
+
+impl Rem for HybridInner {
+    type Output = Self;
+
+    fn rem(self, rhs: Self) -> Self::Output {
+        self.arith(&rhs, u64::checked_rem, Expr::rem)
+    }
+}
+
+impl BitAnd for HybridInner {
+    type Output = Self;
+
+    fn bitand(self, rhs: Self) -> Self::Output {
+        self.bitwise(&rhs, |a, b| a & b, Expr::bvand)
+    }
+}
+
+impl BitOr for HybridInner {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self::Output {
+        self.bitwise(&rhs, |a, b| a | b, Expr::bvor)
+    }
+}
+
+impl BitXor for HybridInner {
+    type Output = Self;
+
+    fn bitxor(self, rhs: Self) -> Self::Output {
+        self.bitwise(&rhs, |a, b| a ^ b, Expr::bvxor)
+    }
+}
+
+// Shifting by 64 bits or more gives 0
+impl Shl for HybridInner {
+    type Output = Self;
+
+    fn shl(self, rhs: Self) -> Self::Output {
+        self.bitwise(
+            &rhs,
+            |a, b| a.checked_shl(b.try_into().unwrap_or(u32::MAX)).unwrap_or(0),
+            Expr::shl,
+        )
+    }
+}
+
+impl Shr for HybridInner {
+    type Output = Self;
+
+    fn shr(self, rhs: Self) -> Self::Output {
+        self.bitwise(
+            &rhs,
+            |a, b| a.checked_shr(b.try_into().unwrap_or(u32::MAX)).unwrap_or(0),
+            Expr::lshr,
+        )
+    }
+}
+
+// Complement of the low 64 bits
+impl Not for HybridInner {
+    type Output = Self;
+
+    fn not(self) -> Self::Output {
         match self {
-            HybridInner::C(c) => {
-                if let Self::C(r) = rhs {
-                    return Self::C(c + r);
-                } else {
-                    panic!("Cannot add concrete and symbolic value together");
-                }
-            }
-            HybridInner::S(s) => {
-                if let Self::S(r) = rhs {
-                    return Self::S(Box::new(s.add(r.as_ref())));
-                } else {
-                    panic!("Cannot add concrete and symbolic value together.");
-                }
-            }
+            Self::C(value) => Self::C(!value),
+            Self::S(IntTerm(value)) => Self::S(IntTerm(value.int_to_bv(64).bvnot().bv_to_int())),
+        }
+    }
+}
+
+// The result of comparing hybrid values
+#[derive(Debug, Clone)]
+pub enum HybridBool {
+    C(bool),
+    // A Bool
+    S(Expr),
+}
+
+impl HybridBool {
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Self::C(value) => Some(*value),
+            Self::S(value) => value.as_bool(),
+        }
+    }
+
+    pub fn to_expr(&self) -> Expr {
+        match self {
+            Self::C(value) => Expr::bool(*value),
+            Self::S(value) => value.clone(),
+        }
+    }
+
+    // Picks a branch without the solver when the condition is concrete
+    pub fn ite(&self, then: &HybridInner, otherwise: &HybridInner) -> HybridInner {
+        match self {
+            Self::C(true) => then.clone(),
+            Self::C(false) => otherwise.clone(),
+            Self::S(condition) => HybridInner::S(IntTerm(
+                condition.ite(&then.to_expr(), &otherwise.to_expr()),
+            )),
         }
     }
 }
-impl Sub for HybridInner<'_> {
+
+impl Not for HybridBool {
     type Output = Self;
 
-    fn sub(self, rhs: Self) -> Self::Output {
+    fn not(self) -> Self::Output {
         match self {
-            HybridInner::C(c) => {
-                if let Self::C(r) = rhs {
-                    return Self::C(c - r);
-                } else {
-                    panic!("Cannot add concrete and symbolic value together");
-                }
-            }
-            HybridInner::S(s) => {
-                if let Self::S(r) = rhs {
-                    return Self::S(Box::new(s.sub(r.as_ref())));
-                } else {
-                    panic!("Cannot add concrete and symbolic value together.");
-                }
-            }
+            Self::C(value) => Self::C(!value),
+            Self::S(value) => Self::S(value.not()),
         }
     }
 }
 
-pub type HybridVal<'a> = Val<HybridInner<'a>>;
+pub type HybridVal = Val<HybridInner>;
+
+impl HybridVal {
+    pub fn concrete(value: u64) -> Self {
+        Self(HybridInner::C(value))
+    }
+
+    // Panics unless `value` is an Int
+    pub fn symbolic(value: Expr) -> Self {
+        Self(HybridInner::symbolic(value))
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        self.0.as_u64()
+    }
+
+    pub fn to_expr(&self) -> Expr {
+        self.0.to_expr()
+    }
+
+    pub fn lt(&self, other: &Self) -> HybridBool {
+        self.0.lt(&other.0)
+    }
+
+    pub fn le(&self, other: &Self) -> HybridBool {
+        self.0.le(&other.0)
+    }
+
+    pub fn gt(&self, other: &Self) -> HybridBool {
+        self.0.gt(&other.0)
+    }
+
+    pub fn ge(&self, other: &Self) -> HybridBool {
+        self.0.ge(&other.0)
+    }
+
+    pub fn _eq(&self, other: &Self) -> HybridBool {
+        self.0._eq(&other.0)
+    }
+}
 
 #[derive(Clone, Debug)]
-pub struct SymbolicBytes<'a> {
-    inner: BV<'a>,
+pub struct SymbolicBytes {
+    inner: Expr,
     size: usize,
 }
 
-impl<'a> SymbolicBytes<'a> {
-    pub fn new(size: usize, name: impl Into<String>, ctx: &'a Context) -> Self {
-        let bv = BV::new_const(ctx, name.into(), size as u32);
+impl SymbolicBytes {
+    pub fn new(size: usize, name: impl Into<String>) -> Self {
+        let bv = Expr::var(name, Sort::BitVec(size as u32));
         Self { inner: bv, size }
     }
 
     pub fn add_u64(&mut self, val: u64) {
-        let val = BV::from_u64(self.inner.get_ctx(), val, self.size as u32);
-        self.inner = self.inner.bvadd(&val);
+        let val = Expr::bv(val, self.size as u32);
+        self.inner = Expr::add(&self.inner, &val);
     }
 }
@@ -0,0 +1,176 @@
+use std::cmp::Ordering;
+use std::ops::{Add, BitAnd, BitOr, BitXor, Div, Mul, Not, Rem, Shl, Shr, Sub};
+
+use crate::expr::{Expr, Sort};
+
+use super::val::ConstantFold;
+use crate::stack::ConcreteEq;
+
+// A W-bit machine word. Arithmetic wraps around modulo 2^W, and `/`, `%` and `>>` are the
+// unsigned operations; the signed ones are methods.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Word<const W: u32>(Expr);
+
+pub type Word8 = Word<8>;
+pub type Word32 = Word<32>;
+pub type Word64 = Word<64>;
+pub type Word256 = Word<256>;
+
+impl<const W: u32> Word<W> {
+    pub fn from_u64(value: u64) -> Self {
+        Self(Expr::bv(value, W))
+    }
+
+    pub fn new_const(name: impl Into<String>) -> Self {
+        Self(Expr::var(name, Sort::BitVec(W)))
+    }
+
+    // Truncates or zero extends `bv` to W bits. Panics unless `bv` is a bitvector.
+    pub fn from_bv(bv: Expr) -> Self {
+        let Sort::BitVec(width) = *bv.sort() else {
+            panic!("a word of sort {}", bv.sort());
+        };
+        Self(match width.cmp(&W) {
+            Ordering::Less => bv.zero_ext(W - width),
+            Ordering::Equal => bv,
+            Ordering::Greater => bv.extract(W - 1, 0),
+        })
+    }
+
+    pub fn bv(&self) -> &Expr {
+        &self.0
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        self.0.simplify().as_u64()
+    }
+
+    pub fn sdiv(&self, other: &Self) -> Self {
+        Self(self.0.sdiv(&other.0))
+    }
+
+    // The remainder takes the sign of `self`
+    pub fn srem(&self, other: &Self) -> Self {
+        Self(self.0.srem(&other.0))
+    }
+
+    // The remainder takes the sign of `other`
+    pub fn smod(&self, other: &Self) -> Self {
+        Self(self.0.smod(&other.0))
+    }
+
+    // Arithmetic shift right
+    pub fn sar(&self, other: &Self) -> Self {
+        Self(self.0.ashr(&other.0))
+    }
+
+    pub fn _eq(&self, other: &Self) -> Expr {
+        self.0._eq(&other.0)
+    }
+
+    pub fn is_zero(&self) -> Expr {
+        self.0._eq(&Expr::bv(0, W))
+    }
+
+    pub fn ult(&self, other: &Self) -> Expr {
+        self.0.lt(&other.0)
+    }
+
+    pub fn ule(&self, other: &Self) -> Expr {
+        self.0.le(&other.0)
+    }
+
+    pub fn ugt(&self, other: &Self) -> Expr {
+        self.0.gt(&other.0)
+    }
+
+    pub fn uge(&self, other: &Self) -> Expr {
+        self.0.ge(&other.0)
+    }
+
+    pub fn slt(&self, other: &Self) -> Expr {
+        self.0.slt(&other.0)
+    }
+
+    pub fn sle(&self, other: &Self) -> Expr {
+        self.0.sle(&other.0)
+    }
+
+    pub fn sgt(&self, other: &Self) -> Expr {
+        self.0.sgt(&other.0)
+    }
+
+    pub fn sge(&self, other: &Self) -> Expr {
+        self.0.sge(&other.0)
+    }
+
+    // Byte `i`, counting from the least significant one. Panics past the width.
+    pub fn byte(&self, i: u32) -> Word8 {
+        assert!(8 * i + 8 <= W, "byte {i} out of range for a {W} bit word");
+        Word(self.0.extract(8 * i + 7, 8 * i))
+    }
+
+    // 1 when `condition` holds, 0 otherwise
+    pub fn from_bool(condition: &Expr) -> Self {
+        Self(condition.ite(&Expr::bv(1, W), &Expr::bv(0, W)))
+    }
+}
+
+impl<const W: u32> From<Expr> for Word<W> {
+    fn from(bv: Expr) -> Self {
+        Self::from_bv(bv)
+    }
+}
+
+impl<const W: u32> From<Word<W>> for Expr {
+    fn from(word: Word<W>) -> Self {
+        word.0
+    }
+}
+
+impl<const W: u32> ConstantFold for Word<W> {
+    fn fold_constants(&self) -> Self {
+        Self(self.0.simplify())
+    }
+}
+
+impl<const W: u32> ConcreteEq for Word<W> {
+    fn concrete_eq(&self, other: &Self) -> Option<bool> {
+        self.0.concrete_eq(&other.0)
+    }
+}
+
+macro_rules! word_binop {
+    ($($trait:ident $method:ident $bv_method:ident),*) => {
+        $(
+            impl<const W: u32> $trait for Word<W> {
+                type Output = Self;
+
+                fn $method(self, rhs: Self) -> Self::Output {
+                    Self(Expr::$bv_method(&self.0, &rhs.0))
+                }
+            }
+        )*
+    };
+}
+
+word_binop!(
+    Add add add,
+    Sub sub sub,
+    Mul mul mul,
+    Div div div,
+    Rem rem rem,
+    BitAnd bitand bvand,
+    BitOr bitor bvor,
+    BitXor bitxor bvxor,
+    Shl shl shl,
+    Shr shr lshr
+);
+
+impl<const W: u32> Not for Word<W> {
+    type Output = Self;
+
+    fn not(self) -> Self::Output {
+        Self(self.0.bvnot())
+    }
+}
@@ -1,3 +1,4 @@
+pub mod expr;
 pub mod instructions;
 pub mod machine;
 pub mod memory;
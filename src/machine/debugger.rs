@@ -0,0 +1,204 @@
+use super::error::MachineError;
+use super::limits::TerminationReason;
+use super::solver::SatResult;
+use super::{BaseMachine, Branch, MachineResult, Program};
+use crate::expr::Expr;
+use crate::instructions::val::ConstantFold;
+use crate::instructions::VMInstruction;
+use crate::memory::{RWMem, ReadOnlyMem};
+use crate::stack::Stack;
+
+// Where `BaseMachine::resume` and `BaseMachine::run_until` stop before executing
+pub enum Breakpoint<'a, I> {
+    Pc(usize),
+    // Stops before any instruction the predicate accepts
+    Instruction(Box<dyn Fn(&I) -> bool + 'a>),
+}
+
+// Why stepping stopped
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Pause {
+    // The machine is about to execute the instruction at this pc
+    Breakpoint(usize),
+    Terminated(TerminationReason),
+}
+
+impl<'a, Mem, MachineStack, I, MemIdx, MemVal, StackVal>
+    BaseMachine<'a, Mem, MachineStack, I, MemIdx, MemVal, StackVal>
+where
+    Mem: RWMem + ReadOnlyMem<Index = MemIdx, MemVal = MemVal> + std::fmt::Debug + Clone + 'a,
+    MachineStack: Stack<StackVal = StackVal> + std::fmt::Debug + Clone + 'a,
+    I: VMInstruction<Mem = Mem, ValStack = MachineStack>,
+    StackVal: Into<MemVal> + ConstantFold,
+{
+    // Replaces the program being stepped through and rewinds the pc. Stack and memory are
+    // left as they are.
+    pub fn load_program(&mut self, pgm: Program<'a, I>) {
+        self.pgm = pgm;
+        self.pc = 0;
+        self.steps = 0;
+        self.stopped = None;
+    }
+
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint<'a, I>) {
+        self.breakpoints.push(breakpoint);
+    }
+
+    pub fn clear_breakpoints(&mut self) {
+        self.breakpoints.clear();
+    }
+
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    pub fn program(&self) -> &Program<'a, I> {
+        &self.pgm
+    }
+
+    pub fn stack(&self) -> &MachineStack {
+        &self.stack
+    }
+
+    pub fn mem(&self) -> &Mem {
+        &self.mem
+    }
+
+    // Constraints accumulated along the stepped path. Always empty for a machine without a
+    // solver, since such a machine only follows concrete branches.
+    pub fn constraints(&self) -> &[Expr] {
+        &self.constraints
+    }
+
+    // Set once the stepped path has ended
+    pub fn stopped(&self) -> Option<&TerminationReason> {
+        self.stopped.as_ref()
+    }
+
+    // Executes the instruction at the current pc. Returns the reason the path ended, if it
+    // did. When an instruction forks, the first feasible successor is followed. A machine
+    // without a solver can only follow branches whose conditions are concrete.
+    // On error the machine is left in the state before the instruction.
+    pub fn step(&mut self) -> MachineResult<Option<TerminationReason>> {
+        if let Some(reason) = &self.stopped {
+            return Ok(Some(reason.clone()));
+        }
+        if self.limits.max_steps.is_some_and(|max| self.steps >= max) {
+            self.stopped = Some(TerminationReason::StepLimit);
+            return Ok(self.stopped.clone());
+        }
+
+        let branch = Branch {
+            path_id: 0,
+            pc: self.pc,
+            stack: self.stack.clone(),
+            mem: self.mem.clone(),
+            constraints: self.constraints().to_vec(),
+            steps: self.steps,
+            depth: 0,
+            trace: vec![],
+        };
+        let solver = self.solver.as_deref_mut();
+        let stopped = match Self::execute(
+            &self.pgm,
+            &mut self.observers,
+            solver,
+            &branch,
+            self.checked_stack,
+            self.limits.max_jump_targets,
+        )? {
+            Some(successors) => {
+                let (next, feasible) = self.choose_stepped(&branch, successors)?;
+                self.pc = next.pc;
+                self.stack = next.stack;
+                self.mem = next.mem;
+                self.constraints = next.constraints;
+                self.steps += 1;
+                if feasible {
+                    None
+                } else {
+                    Some(TerminationReason::Infeasible)
+                }
+            }
+            None if self.pc >= self.pgm.len() => Some(TerminationReason::FellOffProgram),
+            None => {
+                self.steps += 1;
+                Some(TerminationReason::Halted)
+            }
+        };
+        self.stopped = stopped.clone();
+        Ok(stopped)
+    }
+
+    // Steps until the path ends or a breakpoint is reached. Always executes at least one
+    // instruction, so resuming from a breakpoint moves past it.
+    pub fn resume(&mut self) -> MachineResult<Pause> {
+        loop {
+            if let Some(reason) = self.step()? {
+                return Ok(Pause::Terminated(reason));
+            }
+            if self.at_breakpoint() {
+                return Ok(Pause::Breakpoint(self.pc));
+            }
+        }
+    }
+
+    // Like `resume`, with an extra one-off breakpoint at `pc`
+    pub fn run_until(&mut self, pc: usize) -> MachineResult<Pause> {
+        loop {
+            if let Some(reason) = self.step()? {
+                return Ok(Pause::Terminated(reason));
+            }
+            if self.pc == pc || self.at_breakpoint() {
+                return Ok(Pause::Breakpoint(self.pc));
+            }
+        }
+    }
+
+    fn at_breakpoint(&self) -> bool {
+        self.breakpoints.iter().any(|breakpoint| match breakpoint {
+            Breakpoint::Pc(pc) => *pc == self.pc,
+            Breakpoint::Instruction(matches) => self.pgm.get(self.pc).is_some_and(matches),
+        })
+    }
+
+    fn choose_stepped(
+        &mut self,
+        parent: &Branch<MachineStack, Mem>,
+        successors: Vec<Branch<MachineStack, Mem>>,
+    ) -> MachineResult<(Branch<MachineStack, Mem>, bool)> {
+        let solver = match self.solver.as_deref_mut() {
+            Some(solver) => solver,
+            None => return Self::choose_concrete(parent, successors),
+        };
+
+        let mut first = None;
+        for successor in successors {
+            // Nothing new to check when the instruction added no constraints
+            if successor.constraints.len() == parent.constraints.len() {
+                return Ok((successor, true));
+            }
+            let sat = Self::check(solver, &mut self.observers, &successor.constraints);
+            let sat = sat.map_err(|reason| MachineError::Solver {
+                pc: parent.pc,
+                path_id: parent.path_id,
+                reason,
+            })?;
+            match sat {
+                SatResult::Sat => return Ok((successor, true)),
+                SatResult::Unknown => {
+                    return Err(MachineError::Solver {
+                        pc: parent.pc,
+                        path_id: parent.path_id,
+                        reason: solver.reason_unknown(),
+                    })
+                }
+                SatResult::Unsat => {
+                    first.get_or_insert(successor);
+                }
+            }
+        }
+        // `execute` always yields at least one successor
+        Ok((first.unwrap(), false))
+    }
+}
@@ -1,4 +1,45 @@
 use thiserror::{self, Error};
 
-#[derive(Debug, Error)]
-pub enum MachineError {}
+use crate::instructions::error::InstructionError;
+use crate::memory::error::MemoryError;
+use crate::stack::error::StackError;
+
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum MachineError {
+    #[error("Instruction at pc {pc} failed on path {path_id}: {source}")]
+    Instruction {
+        pc: usize,
+        path_id: usize,
+        source: InstructionError,
+    },
+    #[error("Stack update at pc {pc} failed on path {path_id}: {source}")]
+    Stack {
+        pc: usize,
+        path_id: usize,
+        source: StackError,
+    },
+    #[error("Memory update at pc {pc} failed on path {path_id}: {source}")]
+    Memory {
+        pc: usize,
+        path_id: usize,
+        source: MemoryError,
+    },
+    #[error("Solver failed at pc {pc} on path {path_id}: {reason}")]
+    Solver {
+        pc: usize,
+        path_id: usize,
+        reason: String,
+    },
+    #[error("Branch condition at pc {pc} is not concrete on path {path_id}")]
+    SymbolicBranch { pc: usize, path_id: usize },
+    #[error("Jump at pc {pc} has more than {limit} feasible destinations on path {path_id}")]
+    JumpTargets {
+        pc: usize,
+        path_id: usize,
+        limit: usize,
+    },
+    #[error("Memory initialisation failed: {0}")]
+    MemoryInit(MemoryError),
+    #[error("Symbolic execution requires a solver, see `BaseMachine::with_solver`")]
+    NoSolver,
+}
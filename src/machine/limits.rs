@@ -0,0 +1,42 @@
+use std::time::Duration;
+
+use super::error::MachineError;
+
+// Budgets bounding a symbolic run. Every limit is disabled by default.
+#[derive(Debug, Clone, Default)]
+pub struct ExplorationLimits {
+    // Instructions executed along a single path
+    pub max_steps: Option<usize>,
+    // Number of leaves (finished, pruned or cut off paths) collected
+    pub max_paths: Option<usize>,
+    // Number of forks along a single path. A path is cut off once it has taken this many.
+    pub max_depth: Option<usize>,
+    // Feasible destinations of a single symbolic jump. A jump with more fails its path with
+    // `MachineError::JumpTargets`.
+    pub max_jump_targets: Option<usize>,
+    // Time given to each solver query before it returns unknown
+    pub solver_timeout: Option<Duration>,
+    // Wall clock time for the whole run
+    pub deadline: Option<Duration>,
+}
+
+// Why a path stopped executing
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TerminationReason {
+    // An instruction halted the machine
+    Halted,
+    // The pc moved past the end of the program
+    FellOffProgram,
+    // The path constraints were unsatisfiable when the path forked
+    Infeasible,
+    // `ExplorationLimits::max_steps` was reached
+    StepLimit,
+    // `ExplorationLimits::max_depth` was reached
+    DepthLimit,
+    // `ExplorationLimits::max_paths` was reached before the path finished
+    PathLimit,
+    // `ExplorationLimits::deadline` passed before the path finished
+    Timeout,
+    // Executing or applying an instruction failed
+    Error(MachineError),
+}
@@ -1,256 +1,641 @@
+pub mod debugger;
 pub mod error;
-use std::borrow::Borrow;
-use std::rc::Rc;
+pub mod limits;
+pub mod observer;
+pub mod report;
+pub mod smtlib;
+pub mod solver;
+pub mod strategy;
+pub mod symbols;
+use std::time::Instant;
 
+use crate::expr::model::Model;
+use crate::expr::Expr;
+#[cfg(feature = "z3")]
+use crate::expr::Sort;
+use crate::instructions::val::ConstantFold;
 use crate::instructions::*;
-use crate::memory::{ReadOnlyMem, WriteableMem};
-use crate::{
-    memory::{memory_models::MemIntToInt, RWMem},
-    stack::*,
-};
+#[cfg(feature = "z3")]
+use crate::memory::memory_models::BaseMemorySymbolicArray;
+use crate::memory::ReadOnlyMem;
+use crate::{memory::RWMem, stack::*};
+use debugger::Breakpoint;
 use error::MachineError;
-use z3::ast::{Bool, Int};
-use z3::{Context, Model, SatResult, Solver};
+use limits::{ExplorationLimits, TerminationReason};
+use observer::MachineObserver;
+use report::{ExplorationReport, PathOutcome};
+#[cfg(feature = "z3")]
+use solver::IncrementalSolver;
+use solver::{SatResult, SolverBackend};
+use strategy::{DepthFirst, SearchStrategy};
+#[cfg(feature = "z3")]
+use z3::Context;
 
 pub type MachineResult<T> = Result<T, MachineError>;
 
 pub type Program<'a, I> = Vec<I>;
 
-pub struct SymbolicContext<'a> {
-    pub constraints: Vec<z3::ast::Bool<'a>>,
-    pub ctx: Rc<&'a Context>,
+// A pending path of symbolic execution
+#[derive(Debug)]
+pub struct Branch<S, M> {
+    pub path_id: usize,
+    pub pc: usize,
+    pub stack: S,
+    pub mem: M,
+    pub constraints: Vec<Expr>,
+    // Number of instructions executed to reach this branch
+    pub steps: usize,
+    // Number of forks taken to reach this branch
+    pub depth: usize,
+    // The pc of every instruction executed to reach this branch
+    pub trace: Vec<usize>,
 }
 
+type Observers<'a, S, M> = Vec<Box<dyn MachineObserver<S, M> + 'a>>;
+
+type Backend<'a> = Box<dyn SolverBackend + 'a>;
+
 pub struct BaseMachine<'a, Mem, MachineStack, I, MemIdx, MemVal, StackVal>
 where
     Mem: RWMem + ReadOnlyMem<Index = MemIdx, MemVal = MemVal>,
     MachineStack: Stack<StackVal = StackVal>,
-    I: VMInstruction<'a, Mem = Mem, ValStack = MachineStack>,
-    StackVal: Into<MemIdx> + Into<MemVal>,
+    I: VMInstruction<Mem = Mem, ValStack = MachineStack>,
+    StackVal: Into<MemVal>,
 {
     mem: Mem,
     stack: MachineStack,
     pgm: Program<'a, I>,
     pc: usize,
-    context: Option<SymbolicContext<'a>>,
+    // Constraints of the path being stepped through, see `debugger.rs`
+    constraints: Vec<Expr>,
+    strategy: Box<dyn SearchStrategy<MachineStack, Mem> + 'a>,
+    limits: ExplorationLimits,
+    observers: Observers<'a, MachineStack, Mem>,
+    // Stepping state, see `debugger.rs`
+    steps: usize,
+    stopped: Option<TerminationReason>,
+    breakpoints: Vec<Breakpoint<'a, I>>,
+    // Needed by `run_sym`, and to step past symbolic branches
+    solver: Option<Backend<'a>>,
+    // Applies stack records with their pops checked against the stack, see
+    // `with_checked_stack`
+    checked_stack: Option<ApplyStack<MachineStack>>,
 }
 
+type ApplyStack<S> = fn(StackRecord<S>, S) -> StackResult<S>;
+
 impl<'a, Mem, MachineStack, I, MemIdx, MemVal, StackVal>
     BaseMachine<'a, Mem, MachineStack, I, MemIdx, MemVal, StackVal>
 where
-    Mem: RWMem + ReadOnlyMem<Index = MemIdx, MemVal = MemVal> + std::fmt::Debug + Clone,
-    MachineStack: Stack<StackVal = StackVal> + std::fmt::Debug + Clone,
-    I: VMInstruction<'a, Mem = Mem, ValStack = MachineStack>,
-    StackVal: Into<MemIdx> + Into<MemVal>,
+    Mem: RWMem + ReadOnlyMem<Index = MemIdx, MemVal = MemVal> + std::fmt::Debug + Clone + 'a,
+    MachineStack: Stack<StackVal = StackVal> + std::fmt::Debug + Clone + 'a,
+    I: VMInstruction<Mem = Mem, ValStack = MachineStack>,
+    StackVal: Into<MemVal> + ConstantFold,
 {
-    pub fn new(stack: MachineStack, mem_init: Mem::InitArgs) -> Self {
-        let mem = Mem::init(mem_init);
+    // Fails with `MachineError::MemoryInit` when the memory rejects `mem_init`
+    pub fn new(stack: MachineStack, mem_init: Mem::InitArgs) -> MachineResult<Self> {
+        let mem = Mem::init(mem_init).map_err(MachineError::MemoryInit)?;
+        Ok(Self::from_parts(stack, mem))
+    }
+
+    fn from_parts(stack: MachineStack, mem: Mem) -> Self {
         Self {
             mem,
             stack,
             pgm: vec![],
             pc: 0,
-            context: None,
+            constraints: vec![],
+            strategy: Box::new(DepthFirst::new()),
+            limits: ExplorationLimits::default(),
+            observers: vec![],
+            steps: 0,
+            stopped: None,
+            breakpoints: vec![],
+            solver: None,
+            checked_stack: None,
         }
     }
 
-    pub fn run_sym(
-        self,
-        pgm: &Program<'a, I>,
-    ) -> (
-        Vec<(
-            (usize, MachineStack, Mem, Vec<z3::ast::Bool<'a>>),
-            Option<Model<'a>>,
-        )>,
-        Vec<(
-            (usize, MachineStack, Mem, Vec<z3::ast::Bool<'a>>),
-            Option<Model<'a>>,
-        )>,
-    ) {
-        type Branch<'a, S, M> = (usize, S, M, Vec<Bool<'a>>);
-        let stack = self.stack.clone();
-        let mem = self.mem.clone();
-        let context = self.context.unwrap();
-        let execute = |pc: &mut usize,
-                       pgm: &Program<'a, I>,
-                       mut stack: MachineStack,
-                       mut mem: Mem|
-         -> (
-            Option<Branch<MachineStack, Mem>>,
-            Option<Branch<MachineStack, Mem>>,
-        ) {
-            for inst in &pgm[pc.clone()..] {
-                let rec = inst.exec(&stack, &mem).unwrap();
-                println!("EXEC RECORD CONSTRAINTS: {:?}", rec.path_constraints);
-                if rec.halt || pc.clone() == pgm.len() {
-                    return (None, None);
-                } else {
-                    println!("STACK BEFORE APPLY: {:?}", stack);
-                    stack = {
-                        if let Some(stack_diff) = rec.stack_diff {
-                            stack_diff.apply(stack).unwrap()
-                        } else {
-                            stack
-                        }
-                    };
-                    println!("STACK AFTER APPLY: {:?}", stack);
+    pub fn with_strategy(mut self, strategy: impl SearchStrategy<MachineStack, Mem> + 'a) -> Self {
+        self.strategy = Box::new(strategy);
+        self
+    }
 
-                    mem = {
-                        if let Some(mem_diff) = rec.mem_diff {
-                            mem_diff.apply(mem).unwrap()
-                        } else {
-                            mem
-                        }
-                    };
+    pub fn with_limits(mut self, limits: ExplorationLimits) -> Self {
+        self.limits = limits;
+        self
+    }
 
-                    if rec.path_constraints.len() == 1 {
-                        let curr_path_constraints = rec.path_constraints.first().cloned().unwrap();
-                        return (
-                            Some((
-                                pc.clone() + 1,
-                                stack.clone(),
-                                mem.clone(),
-                                curr_path_constraints,
-                            )),
-                            None,
-                        );
-                    }
+    pub fn with_observer(mut self, observer: impl MachineObserver<MachineStack, Mem> + 'a) -> Self {
+        self.observers.push(Box::new(observer));
+        self
+    }
 
-                    if rec.path_constraints.len() == 2 {
-                        let branch_one_rules = rec.path_constraints.first().cloned().unwrap();
-                        let branch_two_rules = rec.path_constraints.get(1).cloned().unwrap();
-
-                        return (
-                            Some((pc.clone() + 1, stack.clone(), mem.clone(), branch_one_rules)),
-                            Some((
-                                rec.pc_change.unwrap(),
-                                stack.clone(),
-                                mem.clone(),
-                                branch_two_rules,
-                            )),
-                        );
-                    }
-                    return (
-                        Some((pc.clone() + 1, stack.clone(), mem.clone(), vec![])),
-                        None,
-                    );
+    // Replaces the memory built from the init args, e.g. with one named by a `SymbolRegistry`
+    pub fn with_memory(mut self, mem: Mem) -> Self {
+        self.mem = mem;
+        self
+    }
+
+    // Checks path constraints with an `IncrementalSolver` in `ctx`
+    #[cfg(feature = "z3")]
+    pub fn with_context(self, ctx: &'a Context) -> Self {
+        self.with_solver(IncrementalSolver::new(ctx))
+    }
+
+    // Sets the solver for path constraint queries
+    pub fn with_solver(mut self, solver: impl SolverBackend + 'a) -> Self {
+        self.solver = Some(Box::new(solver));
+        self
+    }
+
+    fn check(
+        solver: &mut (dyn SolverBackend + 'a),
+        observer: &mut Observers<'a, MachineStack, Mem>,
+        constraints: &[Expr],
+    ) -> Result<SatResult, String> {
+        // A constraint folded to false needs no query, nor do constraints that all folded to
+        // true
+        if constraints.iter().any(|c| c.as_bool() == Some(false)) {
+            return Ok(SatResult::Unsat);
+        }
+        if Self::trivially_sat(constraints) {
+            return Ok(SatResult::Sat);
+        }
+        let sat = solver.check_path(constraints)?;
+        observer.on_solver_query(constraints, sat);
+        Ok(sat)
+    }
+
+    fn trivially_sat(constraints: &[Expr]) -> bool {
+        constraints.iter().all(|c| c.as_bool() == Some(true))
+    }
+
+    fn record(
+        report: &mut ExplorationReport<MachineStack, Mem>,
+        observer: &mut Observers<'a, MachineStack, Mem>,
+        sat: SatResult,
+        outcome: PathOutcome<MachineStack, Mem>,
+    ) {
+        observer.on_leaf(&outcome);
+        report.record(sat, outcome);
+    }
+
+    // Checks a finished path and records it in the report. A path the solver fails on is
+    // recorded as unknown, ending with the solver's error.
+    fn settle(
+        solver: &mut (dyn SolverBackend + 'a),
+        observer: &mut Observers<'a, MachineStack, Mem>,
+        report: &mut ExplorationReport<MachineStack, Mem>,
+        branch: Branch<MachineStack, Mem>,
+        reason: TerminationReason,
+    ) {
+        let (sat, reason) = match Self::check(solver, observer, &branch.constraints) {
+            Ok(sat) => (sat, reason),
+            Err(reason) => {
+                let error = MachineError::Solver {
+                    pc: branch.pc,
+                    path_id: branch.path_id,
+                    reason,
+                };
+                (SatResult::Unknown, TerminationReason::Error(error))
+            }
+        };
+        let model = match sat {
+            // Any assignment will do, and the solver was not asked for one
+            SatResult::Sat if Self::trivially_sat(&branch.constraints) => Some(Model::default()),
+            SatResult::Sat => solver.get_model(),
+            _ => None,
+        };
+        let outcome = PathOutcome::from_branch(branch, model, reason);
+        Self::record(report, observer, sat, outcome);
+    }
+
+    // Executes the instruction at the branch's pc. Returns the successor branches, or None
+    // when the path ends here.
+    // Symbolic jump targets are resolved with `solver`, into at most `max_jump_targets`
+    // destinations, or must simplify to a concrete value when there is none.
+    fn execute(
+        pgm: &Program<'a, I>,
+        observer: &mut Observers<'a, MachineStack, Mem>,
+        mut solver: Option<&mut (dyn SolverBackend + 'a)>,
+        branch: &Branch<MachineStack, Mem>,
+        checked_stack: Option<ApplyStack<MachineStack>>,
+        max_jump_targets: Option<usize>,
+    ) -> MachineResult<Option<Vec<Branch<MachineStack, Mem>>>> {
+        let (pc, path_id) = (branch.pc, branch.path_id);
+        let inst = match pgm.get(pc) {
+            Some(inst) => inst,
+            None => return Ok(None),
+        };
+        observer.before_step(path_id, pc, &branch.stack, &branch.mem);
+        let rec =
+            inst.exec(&branch.stack, &branch.mem)
+                .map_err(|source| MachineError::Instruction {
+                    pc,
+                    path_id,
+                    source,
+                })?;
+        if rec.halt {
+            return Ok(None);
+        }
+        let stack = {
+            if let Some(stack_diff) = rec.stack_diff {
+                // Values are folded before they reach the stack, and popped values so that
+                // checked pops compare folded values
+                let stack_diff = stack_diff.map_values(|v| v.fold_constants());
+                match checked_stack {
+                    Some(apply_checked) => apply_checked(stack_diff, branch.stack.clone()),
+                    None => stack_diff.apply(branch.stack.clone()),
                 }
+                .map_err(|source| MachineError::Stack {
+                    pc,
+                    path_id,
+                    source,
+                })?
+            } else {
+                branch.stack.clone()
+            }
+        };
+
+        let mem = {
+            if let Some(mem_diff) = rec.mem_diff {
+                mem_diff
+                    .apply(branch.mem.clone())
+                    .map_err(|source| MachineError::Memory {
+                        pc,
+                        path_id,
+                        source,
+                    })?
+            } else {
+                branch.mem.clone()
             }
-            return (None, None);
         };
+        observer.after_step(branch.path_id, branch.pc, &stack, &mem);
 
-        let mut trace_tree: Vec<Branch<MachineStack, Mem>> = vec![];
-        trace_tree.push((0, stack.clone(), mem.clone(), vec![]));
-        let mut leaves: Vec<Branch<MachineStack, Mem>> = vec![];
-        loop {
-            let start_branch = trace_tree.pop();
-            if let Some(start_branch) = start_branch {
-                let (mut pc, stack, mem, mut constraints) = start_branch;
-                let branches = execute(&mut pc, pgm, stack.clone(), mem.clone());
-                println!("BRANCHES AFTER ONE EXEC: {:?}", branches);
-                match branches {
-                    (None, None) => {
-                        // A branch reached the end of program or halted; move to next traversal and
-                        // store this possible end state
-                        leaves.push((pc, stack, mem, constraints));
+        // Constraints are constant folded before the solver sees them. Trivially true ones are
+        // dropped, and a successor whose condition folds to false is elided unless no
+        // successor is feasible, so branches on concrete conditions never fork.
+        let mut targets = vec![];
+        let mut infeasible = None;
+        if rec.successors.is_empty() {
+            targets.push((pc + 1, branch.constraints.clone()));
+        }
+        for Successor {
+            target,
+            constraints: new_constraints,
+        } in rec.successors
+        {
+            let mut constraints = branch.constraints.clone();
+            let mut feasible = true;
+            for constraint in new_constraints {
+                let constraint = constraint.simplify();
+                match constraint.as_bool() {
+                    Some(true) => continue,
+                    Some(false) => feasible = false,
+                    None => {}
+                }
+                constraints.push(constraint);
+            }
+            if !feasible {
+                let target = match target {
+                    JumpTarget::Next => pc + 1,
+                    JumpTarget::Concrete(target) => target,
+                    JumpTarget::Symbolic(_) => pc,
+                };
+                infeasible.get_or_insert((target, constraints));
+                continue;
+            }
+            match target {
+                JumpTarget::Next => targets.push((pc + 1, constraints)),
+                JumpTarget::Concrete(target) => targets.push((target, constraints)),
+                JumpTarget::Symbolic(dest) => {
+                    let dest = dest.simplify();
+                    if let Some(target) = dest.as_u64() {
+                        targets.push((target as usize, constraints));
+                        continue;
                     }
-                    (None, Some(_)) => {
-                        panic!("This should never happen");
+                    let solver = solver
+                        .as_deref_mut()
+                        .ok_or(MachineError::SymbolicBranch { pc, path_id })?;
+                    let end = Expr::int(pgm.len() as u64);
+                    // One value past the limit tells whether the jump has more destinations
+                    let max = max_jump_targets.map_or(pgm.len(), |limit| limit + 1);
+                    let values = solver
+                        .enumerate_values(&constraints, &dest, pgm.len() as u64, max)
+                        .map_err(|reason| MachineError::Solver {
+                            pc,
+                            path_id,
+                            reason,
+                        })?;
+                    if let Some(limit) = max_jump_targets.filter(|&limit| values.len() > limit) {
+                        return Err(MachineError::JumpTargets { pc, path_id, limit });
                     }
-                    (Some(branch), None) => {
-                        // Only one possible path but constraints were added
-                        constraints.extend(branch.3);
-                        trace_tree.push((branch.0, branch.1, branch.2, constraints));
+                    // Destinations outside the program share one successor, which falls off
+                    // the end of it, unless the solver rules them out
+                    let mut outside = constraints.clone();
+                    outside.push(dest.lt(&Expr::int(0)).or(&dest.ge(&end)));
+                    let falls_off = Self::check(solver, observer, &outside).map_err(|reason| {
+                        MachineError::Solver {
+                            pc,
+                            path_id,
+                            reason,
+                        }
+                    })? != SatResult::Unsat;
+                    if values.is_empty() && !falls_off {
+                        // No destination is consistent with the path
+                        let mut constraints = constraints.clone();
+                        constraints.push(Expr::bool(false));
+                        infeasible.get_or_insert((pc, constraints));
                     }
-                    (Some(b1), Some(b2)) => {
-                        // Branch condition has been introduced; traverse down b1 then b2
-                        let mut b2_constraints = constraints.clone();
-                        b2_constraints.extend(b2.3);
-                        constraints.extend(b1.3);
-
-                        trace_tree.push((b2.0, b2.1, b2.2, b2_constraints));
-                        trace_tree.push((b1.0, b1.1, b1.2, constraints));
+                    for value in values {
+                        let mut constraints = constraints.clone();
+                        constraints.push(dest._eq(&Expr::int(value)));
+                        targets.push((value as usize, constraints));
+                    }
+                    if falls_off {
+                        targets.push((pgm.len(), outside));
                     }
                 }
-            } else {
-                break;
             }
         }
+        if targets.is_empty() {
+            targets.extend(infeasible);
+        }
 
-        // println!("Final LEAVES: {:?}", leaves);
+        let depth = branch.depth + usize::from(targets.len() > 1);
+        let mut trace = branch.trace.clone();
+        trace.push(pc);
+        let successors = targets
+            .into_iter()
+            .map(|(pc, constraints)| Branch {
+                path_id,
+                pc,
+                stack: stack.clone(),
+                mem: mem.clone(),
+                constraints,
+                steps: branch.steps + 1,
+                depth,
+                trace: trace.clone(),
+            })
+            .collect();
+        Ok(Some(successors))
+    }
 
-        let mut reachable = vec![];
-        let mut unreachable = vec![];
+    // Fails with `MachineError::NoSolver` unless a solver was set, see `with_solver`
+    pub fn run_sym(
+        self,
+        pgm: &Program<'a, I>,
+    ) -> MachineResult<ExplorationReport<MachineStack, Mem>> {
+        let started = Instant::now();
+        let limits = self.limits;
+        let checked_stack = self.checked_stack;
+        let deadline = limits.deadline.map(|d| started + d);
+        let mut solver = self.solver.ok_or(MachineError::NoSolver)?;
+        if let Some(timeout) = limits.solver_timeout {
+            solver.set_timeout(timeout);
+        }
+        let mut strategy = self.strategy;
+        let mut observer = self.observers;
 
-        for leaf in leaves {
-            let solver = Solver::new(&context.ctx);
-            let constraints = &leaf.3;
-            for constraint in constraints {
-                solver.assert(&constraint);
-            }
-            let sat = solver.check();
-            if let SatResult::Sat = sat {
-                reachable.push((leaf, solver.get_model()));
+        strategy.push(Branch {
+            path_id: 0,
+            pc: 0,
+            stack: self.stack,
+            mem: self.mem,
+            constraints: vec![],
+            steps: 0,
+            depth: 0,
+            trace: vec![],
+        });
+        let mut next_path_id = 1;
+        let mut report = ExplorationReport::new();
+        let paths_spent = |report: &ExplorationReport<MachineStack, Mem>| {
+            limits.max_paths.is_some_and(|max| report.len() >= max)
+        };
+        while !paths_spent(&report) {
+            let Some(branch) = strategy.pop() else {
+                break;
+            };
+            let out_of_budget = if deadline.is_some_and(|d| Instant::now() >= d) {
+                Some(TerminationReason::Timeout)
+            } else if limits.max_steps.is_some_and(|max| branch.steps >= max) {
+                Some(TerminationReason::StepLimit)
+            } else if limits.max_depth.is_some_and(|max| branch.depth >= max) {
+                Some(TerminationReason::DepthLimit)
             } else {
-                unreachable.push((leaf, None));
+                None
+            };
+            if let Some(reason) = out_of_budget {
+                if reason == TerminationReason::Timeout {
+                    // Out of time to ask the solver about it
+                    let outcome = PathOutcome::from_branch(branch, None, reason);
+                    Self::record(&mut report, &mut observer, SatResult::Unknown, outcome);
+                } else {
+                    Self::settle(solver.as_mut(), &mut observer, &mut report, branch, reason);
+                }
+                continue;
+            }
+
+            match Self::execute(
+                pgm,
+                &mut observer,
+                Some(solver.as_mut()),
+                &branch,
+                checked_stack,
+                limits.max_jump_targets,
+            ) {
+                Ok(Some(mut successors)) => {
+                    report.stats.steps += 1;
+                    if successors.len() > 1 {
+                        report.stats.forks += 1;
+                        for successor in successors.iter_mut() {
+                            successor.path_id = next_path_id;
+                            next_path_id += 1;
+                        }
+                        observer.on_fork(branch.path_id, branch.pc, &successors);
+                    }
+                    // Pushed in reverse so that depth first traverses the fall through
+                    // branch before the jump
+                    for successor in successors.into_iter().rev() {
+                        // Prune as soon as a new constraint makes the path infeasible rather
+                        // than executing the subtree to its leaves
+                        let checked = (!paths_spent(&report)
+                            && successor.constraints.len() > branch.constraints.len())
+                        .then(|| {
+                            Self::check(solver.as_mut(), &mut observer, &successor.constraints)
+                        });
+                        match checked {
+                            Some(Ok(SatResult::Unsat)) => {
+                                report.stats.pruned += 1;
+                                let outcome = PathOutcome::from_branch(
+                                    successor,
+                                    None,
+                                    TerminationReason::Infeasible,
+                                );
+                                Self::record(&mut report, &mut observer, SatResult::Unsat, outcome);
+                            }
+                            Some(Err(reason)) => {
+                                // Failed while checking the fork at the parent's pc
+                                let error = MachineError::Solver {
+                                    pc: branch.pc,
+                                    path_id: successor.path_id,
+                                    reason,
+                                };
+                                let reason = TerminationReason::Error(error);
+                                let outcome = PathOutcome::from_branch(successor, None, reason);
+                                Self::record(
+                                    &mut report,
+                                    &mut observer,
+                                    SatResult::Unknown,
+                                    outcome,
+                                );
+                            }
+                            _ => strategy.push(successor),
+                        }
+                    }
+                }
+                Ok(None) => {
+                    // A branch reached the end of program or halted; store this possible end
+                    // state
+                    let mut branch = branch;
+                    let reason = if branch.pc >= pgm.len() {
+                        TerminationReason::FellOffProgram
+                    } else {
+                        report.stats.steps += 1;
+                        branch.trace.push(branch.pc);
+                        TerminationReason::Halted
+                    };
+                    Self::settle(solver.as_mut(), &mut observer, &mut report, branch, reason);
+                }
+                Err(e) => {
+                    // Only this path is abandoned; the error is reported as its leaf so a
+                    // reachable error can be told apart from an infeasible one
+                    let reason = TerminationReason::Error(e);
+                    Self::settle(solver.as_mut(), &mut observer, &mut report, branch, reason);
+                }
             }
         }
-        println!("Unreachable leaves: {:?}", unreachable);
-        println!("Reachable leaves: {:?}", reachable);
-        return (reachable, unreachable);
+
+        // The path budget is spent; whatever is still pending is cut off without asking the
+        // solver about it
+        while let Some(branch) = strategy.pop() {
+            let outcome = PathOutcome::from_branch(branch, None, TerminationReason::PathLimit);
+            report.cut_off.push(outcome);
+        }
+        report.stats.solver_queries = solver.queries();
+        report.stats.elapsed = started.elapsed();
+        Ok(report)
     }
-    pub fn run(self, pgm: &Program<'a, I>) -> Option<MachineStack::StackVal>
-    where
-        Mem: Clone,
-        MachineStack: Clone,
-    {
-        let mut stack = self.stack.clone();
-        let mut mem = self.mem.clone();
-
-        for inst in pgm {
-            let rec = inst.exec(&stack, &self.mem).unwrap();
-            stack = {
-                if let Some(stack_diff) = rec.stack_diff {
-                    stack_diff.apply(stack).unwrap()
-                } else {
-                    stack
-                }
-            };
 
-            mem = {
-                if let Some(mem_diff) = rec.mem_diff {
-                    mem_diff.apply(mem).unwrap()
-                } else {
-                    mem
+    // Picks the successor whose new constraints all hold concretely. The flag is false when
+    // none of them hold, e.g. a failed assertion, in which case the first successor is
+    // returned as the point where execution stopped.
+    fn choose_concrete(
+        parent: &Branch<MachineStack, Mem>,
+        successors: Vec<Branch<MachineStack, Mem>>,
+    ) -> MachineResult<(Branch<MachineStack, Mem>, bool)> {
+        let mut first = None;
+        for successor in successors {
+            let mut holds = true;
+            for constraint in &successor.constraints[parent.constraints.len()..] {
+                match constraint.simplify().as_bool() {
+                    Some(true) => {}
+                    Some(false) => holds = false,
+                    None => {
+                        return Err(MachineError::SymbolicBranch {
+                            pc: parent.pc,
+                            path_id: parent.path_id,
+                        })
+                    }
                 }
-            };
+            }
+            if holds {
+                return Ok((successor, true));
+            }
+            first.get_or_insert(successor);
         }
+        // `execute` always yields at least one successor
+        Ok((first.unwrap(), false))
+    }
+
+    // Executes the program along the single path its concrete values select, following
+    // jumps until it halts or falls off the end. Uses the same `ExecRecord` semantics as
+    // `run_sym`, so both agree for the same `VMInstruction` implementation.
+    pub fn run(self, pgm: &Program<'a, I>) -> MachineResult<PathOutcome<MachineStack, Mem>> {
+        let deadline = self.limits.deadline.map(|d| Instant::now() + d);
+        let max_steps = self.limits.max_steps;
+        let checked_stack = self.checked_stack;
+        let mut observer = self.observers;
+        let mut branch = Branch {
+            path_id: 0,
+            pc: 0,
+            stack: self.stack,
+            mem: self.mem,
+            constraints: vec![],
+            steps: 0,
+            depth: 0,
+            trace: vec![],
+        };
+
+        let reason = loop {
+            if deadline.is_some_and(|d| Instant::now() >= d) {
+                break TerminationReason::Timeout;
+            }
+            if max_steps.is_some_and(|max| branch.steps >= max) {
+                break TerminationReason::StepLimit;
+            }
+            match Self::execute(pgm, &mut observer, None, &branch, checked_stack, None)? {
+                Some(successors) => {
+                    let (next, feasible) = Self::choose_concrete(&branch, successors)?;
+                    branch = next;
+                    if !feasible {
+                        break TerminationReason::Infeasible;
+                    }
+                }
+                None if branch.pc >= pgm.len() => break TerminationReason::FellOffProgram,
+                None => {
+                    branch.trace.push(branch.pc);
+                    break TerminationReason::Halted;
+                }
+            }
+        };
+
+        let outcome = PathOutcome::from_branch(branch, None, reason);
+        observer.on_leaf(&outcome);
+        Ok(outcome)
+    }
+}
 
-        stack.peek(0)
+// Checking pops needs to compare and print stack values
+impl<'a, Mem, MachineStack, I, MemIdx, MemVal, StackVal>
+    BaseMachine<'a, Mem, MachineStack, I, MemIdx, MemVal, StackVal>
+where
+    Mem: RWMem + ReadOnlyMem<Index = MemIdx, MemVal = MemVal> + std::fmt::Debug + Clone + 'a,
+    MachineStack: Stack<StackVal = StackVal> + std::fmt::Debug + Clone + 'a,
+    I: VMInstruction<Mem = Mem, ValStack = MachineStack>,
+    StackVal: Into<MemVal> + ConstantFold + ConcreteEq + std::fmt::Debug,
+{
+    // Checks every `StackOpRecord::Pop` an instruction records against the value actually on
+    // top of the stack, and ends the path with `StackError::PopMismatch` when they provably
+    // differ. Catches instructions that peek the wrong operands.
+    pub fn with_checked_stack(mut self) -> Self {
+        self.checked_stack = Some(StackRecord::apply_checked);
+        self
     }
 }
 
 // Implement machine initialization for a specific memory model
+#[cfg(feature = "z3")]
 impl<'a, MachineStack, I>
-    BaseMachine<'a, MemIntToInt<'a>, MachineStack, I, Int<'a>, Int<'a>, Int<'a>>
+    BaseMachine<'a, BaseMemorySymbolicArray, MachineStack, I, Expr, Expr, Expr>
 where
-    MachineStack: Stack<StackVal = Int<'a>>,
-    I: VMInstruction<'a, Mem = MemIntToInt<'a>, ValStack = MachineStack>,
+    MachineStack: Stack<StackVal = Expr> + std::fmt::Debug + Clone + 'a,
+    I: VMInstruction<Mem = BaseMemorySymbolicArray, ValStack = MachineStack>,
 {
-    // For symbolic memory
-    pub fn new_with_ctx(stack: MachineStack, mem_init_args: Rc<&'a Context>) -> Self {
-        let mem = MemIntToInt::init(mem_init_args.clone());
-        let ctx = SymbolicContext {
-            constraints: vec![],
-            ctx: mem_init_args.clone(),
-        };
-
-        Self {
-            mem,
-            stack,
-            pgm: vec![],
-            pc: 0,
-            context: Some(ctx),
-        }
+    // For symbolic memory from Int to Int, with paths checked by an `IncrementalSolver` in `ctx`
+    pub fn new_with_ctx(stack: MachineStack, ctx: &'a Context) -> Self {
+        let mem = BaseMemorySymbolicArray::fresh("memory", Sort::Int, Sort::Int);
+        Self::from_parts(stack, mem).with_context(ctx)
     }
 }
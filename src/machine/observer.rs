@@ -0,0 +1,93 @@
+use super::report::PathOutcome;
+use super::solver::SatResult;
+use super::Branch;
+use crate::expr::Expr;
+
+// Hooks into a symbolic run. Every callback defaults to doing nothing, so an observer only
+// implements the events it cares about.
+pub trait MachineObserver<S, M> {
+    // Called with the state an instruction is about to execute against
+    fn before_step(&mut self, _path_id: usize, _pc: usize, _stack: &S, _mem: &M) {}
+
+    // Called with the state after the instruction's diffs have been applied
+    fn after_step(&mut self, _path_id: usize, _pc: usize, _stack: &S, _mem: &M) {}
+
+    // Called when the instruction at `pc` splits a path into several successors
+    fn on_fork(&mut self, _path_id: usize, _pc: usize, _successors: &[Branch<S, M>]) {}
+
+    fn on_leaf(&mut self, _outcome: &PathOutcome<S, M>) {}
+
+    fn on_solver_query(&mut self, _constraints: &[Expr], _result: SatResult) {}
+}
+
+// Forwards every event to each observer in turn
+impl<'a, S, M> MachineObserver<S, M> for Vec<Box<dyn MachineObserver<S, M> + 'a>> {
+    fn before_step(&mut self, path_id: usize, pc: usize, stack: &S, mem: &M) {
+        for observer in self.iter_mut() {
+            observer.before_step(path_id, pc, stack, mem);
+        }
+    }
+
+    fn after_step(&mut self, path_id: usize, pc: usize, stack: &S, mem: &M) {
+        for observer in self.iter_mut() {
+            observer.after_step(path_id, pc, stack, mem);
+        }
+    }
+
+    fn on_fork(&mut self, path_id: usize, pc: usize, successors: &[Branch<S, M>]) {
+        for observer in self.iter_mut() {
+            observer.on_fork(path_id, pc, successors);
+        }
+    }
+
+    fn on_leaf(&mut self, outcome: &PathOutcome<S, M>) {
+        for observer in self.iter_mut() {
+            observer.on_leaf(outcome);
+        }
+    }
+
+    fn on_solver_query(&mut self, constraints: &[Expr], result: SatResult) {
+        for observer in self.iter_mut() {
+            observer.on_solver_query(constraints, result);
+        }
+    }
+}
+
+// Reports every event through the `log` crate: steps at trace level, everything else at
+// debug level
+#[cfg(feature = "log")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LogObserver;
+
+#[cfg(feature = "log")]
+impl<S, M> MachineObserver<S, M> for LogObserver
+where
+    S: std::fmt::Debug,
+    M: std::fmt::Debug,
+{
+    fn before_step(&mut self, path_id: usize, pc: usize, stack: &S, mem: &M) {
+        log::trace!("path {path_id} pc {pc} before: stack {stack:?} mem {mem:?}");
+    }
+
+    fn after_step(&mut self, path_id: usize, pc: usize, stack: &S, mem: &M) {
+        log::trace!("path {path_id} pc {pc} after: stack {stack:?} mem {mem:?}");
+    }
+
+    fn on_fork(&mut self, path_id: usize, pc: usize, successors: &[Branch<S, M>]) {
+        let targets = successors.iter().map(|b| b.pc).collect::<Vec<_>>();
+        log::debug!("path {path_id} forked at pc {pc} into pcs {targets:?}");
+    }
+
+    fn on_leaf(&mut self, outcome: &PathOutcome<S, M>) {
+        log::debug!(
+            "path {} ended at pc {}: {:?}",
+            outcome.path_id,
+            outcome.pc,
+            outcome.reason
+        );
+    }
+
+    fn on_solver_query(&mut self, constraints: &[Expr], result: SatResult) {
+        log::debug!("solver: {result:?} for {constraints:?}");
+    }
+}
@@ -0,0 +1,170 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use super::limits::TerminationReason;
+use super::solver::SatResult;
+use super::Branch;
+use crate::expr::model::Model;
+use crate::expr::{smtlib, Expr};
+use crate::memory::SymbolicMem;
+
+// Final state of one explored path
+#[derive(Debug)]
+pub struct PathOutcome<S, M> {
+    pub path_id: usize,
+    pub pc: usize,
+    pub stack: S,
+    pub mem: M,
+    pub constraints: Vec<Expr>,
+    // Satisfying assignment for `constraints` when the path is reachable
+    pub model: Option<Model>,
+    pub reason: TerminationReason,
+    // Number of forks taken along the path
+    pub depth: usize,
+    // The pc of every instruction executed along the path, in order
+    pub trace: Vec<usize>,
+}
+
+impl<S, M> PathOutcome<S, M> {
+    pub(crate) fn from_branch(
+        branch: Branch<S, M>,
+        model: Option<Model>,
+        reason: TerminationReason,
+    ) -> Self {
+        Self {
+            path_id: branch.path_id,
+            pc: branch.pc,
+            stack: branch.stack,
+            mem: branch.mem,
+            constraints: branch.constraints,
+            model,
+            reason,
+            depth: branch.depth,
+            trace: branch.trace,
+        }
+    }
+}
+
+impl<S, M: SymbolicMem> PathOutcome<S, M> {
+    // The path constraints as a standalone SMT-LIB2 script declaring every symbolic input,
+    // including the memory array
+    pub fn to_smtlib(&self) -> String {
+        self.smtlib_script(None)
+    }
+
+    fn smtlib_script(&self, status: Option<SatResult>) -> String {
+        let header = format!(
+            "; path {} ended at pc {}: {:?}\n",
+            self.path_id, self.pc, self.reason
+        );
+        let mut script = header + "(set-option :produce-models true)\n";
+        let status = match status {
+            Some(SatResult::Sat) => "sat",
+            Some(SatResult::Unsat) => "unsat",
+            _ => "unknown",
+        };
+        script.push_str(&format!("(set-info :status {status})\n"));
+        let mut terms = self.constraints.clone();
+        terms.extend(self.mem.terms());
+        script.push_str(&smtlib::declarations(&terms));
+        for constraint in &self.constraints {
+            script.push_str(&format!("(assert {constraint})\n"));
+        }
+        script.push_str("(check-sat)\n");
+        if status == "sat" {
+            script.push_str("(get-model)\n");
+        }
+        script
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ExplorationStats {
+    // Number of leaves collected
+    pub paths: usize,
+    // Instructions executed across every path
+    pub steps: usize,
+    // Instructions that split a path into more than one successor
+    pub forks: usize,
+    // Paths cut at a fork because their constraints were unsatisfiable
+    pub pruned: usize,
+    pub solver_queries: usize,
+    pub elapsed: Duration,
+}
+
+// Every leaf of a symbolic run, grouped by whether its path constraints are satisfiable
+#[derive(Debug)]
+pub struct ExplorationReport<S, M> {
+    pub reachable: Vec<PathOutcome<S, M>>,
+    pub unreachable: Vec<PathOutcome<S, M>>,
+    // Paths the solver could not decide, or that were never checked because the deadline
+    // passed
+    pub unknown: Vec<PathOutcome<S, M>>,
+    // Branches still pending once `max_paths` leaves were collected. They are not leaves and
+    // were never checked with the solver.
+    pub cut_off: Vec<PathOutcome<S, M>>,
+    pub stats: ExplorationStats,
+}
+
+impl<S, M> ExplorationReport<S, M> {
+    pub(crate) fn new() -> Self {
+        Self {
+            reachable: vec![],
+            unreachable: vec![],
+            unknown: vec![],
+            cut_off: vec![],
+            stats: ExplorationStats::default(),
+        }
+    }
+
+    pub(crate) fn record(&mut self, sat: SatResult, outcome: PathOutcome<S, M>) {
+        self.stats.paths += 1;
+        match sat {
+            SatResult::Sat => self.reachable.push(outcome),
+            SatResult::Unsat => self.unreachable.push(outcome),
+            SatResult::Unknown => self.unknown.push(outcome),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.reachable.len() + self.unreachable.len() + self.unknown.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Writes each leaf's path constraints to `path_<id>.smt2` in `dir`, creating it if needed.
+    // Returns the files written.
+    pub fn write_smtlib(&self, dir: impl AsRef<Path>) -> io::Result<Vec<PathBuf>>
+    where
+        M: SymbolicMem,
+    {
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir)?;
+        let groups = [
+            (&self.reachable, SatResult::Sat),
+            (&self.unreachable, SatResult::Unsat),
+            (&self.unknown, SatResult::Unknown),
+        ];
+        let mut written = vec![];
+        for (outcomes, status) in groups {
+            for outcome in outcomes {
+                let file = dir.join(format!("path_{}.smt2", outcome.path_id));
+                fs::write(&file, outcome.smtlib_script(Some(status)))?;
+                written.push(file);
+            }
+        }
+        Ok(written)
+    }
+
+    // Every leaf regardless of reachability
+    pub fn paths(&self) -> impl Iterator<Item = &PathOutcome<S, M>> {
+        self.reachable
+            .iter()
+            .chain(self.unreachable.iter())
+            .chain(self.unknown.iter())
+    }
+}
@@ -0,0 +1,181 @@
+use std::ffi::OsStr;
+use std::io::{self, BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::time::Duration;
+
+use super::solver::{SatResult, SolverBackend};
+use crate::expr::model::Model;
+use crate::expr::smtlib::{self, SExpr};
+use crate::expr::Expr;
+
+// A solver binary driven through SMT-LIB2 on its stdin, e.g. `z3 -in` or `cvc5
+// --incremental`. Every query is sent whole in a fresh scope. Models are read back with
+// `get-value` for the terms `Model::queries` asks for.
+pub struct SmtLibSolver {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+    scope_open: bool,
+    model: Option<Model>,
+    reason_unknown: String,
+    queries: usize,
+}
+
+impl SmtLibSolver {
+    // Starts `program` with `args`
+    pub fn new(program: impl AsRef<OsStr>, args: &[&str]) -> io::Result<Self> {
+        let mut child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let stdin = child.stdin.take().expect("stdin is piped");
+        let stdout = BufReader::new(child.stdout.take().expect("stdout is piped"));
+        let mut solver = Self {
+            child,
+            stdin,
+            stdout,
+            scope_open: false,
+            model: None,
+            reason_unknown: String::new(),
+            queries: 0,
+        };
+        // Every command then answers, so responses can be matched to commands
+        solver.expect_success("(set-option :print-success true)")?;
+        solver.expect_success("(set-option :produce-models true)")?;
+        Ok(solver)
+    }
+
+    fn query(&mut self, constraints: &[Expr]) -> io::Result<SatResult> {
+        if self.scope_open {
+            self.expect_success("(pop 1)")?;
+            self.scope_open = false;
+        }
+        self.expect_success("(push 1)")?;
+        self.scope_open = true;
+
+        for declaration in smtlib::declarations(constraints).lines() {
+            self.expect_success(declaration)?;
+        }
+        for constraint in constraints {
+            self.expect_success(&format!("(assert {constraint})"))?;
+        }
+
+        match self.command("(check-sat)")? {
+            SExpr::Atom(result) if result == "sat" => {
+                self.model = Some(self.read_model(constraints)?);
+                Ok(SatResult::Sat)
+            }
+            SExpr::Atom(result) if result == "unsat" => Ok(SatResult::Unsat),
+            SExpr::Atom(result) if result == "unknown" => {
+                self.reason_unknown = match self.command("(get-info :reason-unknown)")? {
+                    SExpr::List(info) if info.len() == 2 => info[1].to_string(),
+                    _ => String::new(),
+                };
+                Ok(SatResult::Unknown)
+            }
+            other => Err(invalid_data(format!(
+                "unexpected check-sat response {other}"
+            ))),
+        }
+    }
+
+    fn read_model(&mut self, constraints: &[Expr]) -> io::Result<Model> {
+        let queries = Model::queries(constraints);
+        if queries.is_empty() {
+            return Ok(Model::default());
+        }
+        let terms = queries.iter().map(Expr::to_string);
+        let request = format!("(get-value ({}))", terms.collect::<Vec<_>>().join(" "));
+        let values = match self.command(&request)? {
+            SExpr::List(values) if values.len() == queries.len() => values,
+            other => {
+                return Err(invalid_data(format!(
+                    "unexpected get-value response {other}"
+                )))
+            }
+        };
+        let values = queries
+            .iter()
+            .zip(&values)
+            .map(|(query, value)| {
+                match value {
+                    SExpr::List(pair) if pair.len() == 2 => smtlib::value(&pair[1], query.sort()),
+                    _ => None,
+                }
+                .ok_or_else(|| invalid_data(format!("unexpected value {value} for {query}")))
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+        Ok(Model::from_values(&queries, &values))
+    }
+
+    fn expect_success(&mut self, command: &str) -> io::Result<()> {
+        match self.command(command)? {
+            SExpr::Atom(response) if response == "success" => Ok(()),
+            other => Err(invalid_data(format!("{command}: {other}"))),
+        }
+    }
+
+    fn command(&mut self, command: &str) -> io::Result<SExpr> {
+        writeln!(self.stdin, "{command}")?;
+        self.stdin.flush()?;
+        self.read_response()
+    }
+
+    // Reads lines until they hold one complete s-expression
+    fn read_response(&mut self) -> io::Result<SExpr> {
+        let mut response = String::new();
+        loop {
+            if self.stdout.read_line(&mut response)? == 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "solver exited",
+                ));
+            }
+            if let Some(sexpr) = SExpr::parse(&response) {
+                return Ok(sexpr);
+            }
+        }
+    }
+}
+
+impl SolverBackend for SmtLibSolver {
+    // Failing to write a command or read a response back is an error rather than an
+    // unknown result
+    fn check_path(&mut self, constraints: &[Expr]) -> Result<SatResult, String> {
+        self.queries += 1;
+        self.model = None;
+        self.query(constraints).map_err(|e| e.to_string())
+    }
+
+    fn get_model(&self) -> Option<Model> {
+        self.model.clone()
+    }
+
+    fn reason_unknown(&self) -> String {
+        self.reason_unknown.clone()
+    }
+
+    fn queries(&self) -> usize {
+        self.queries
+    }
+
+    // `:timeout` is not a standard option; solvers that do not support it ignore it
+    fn set_timeout(&mut self, timeout: Duration) {
+        let _ = self.command(&format!("(set-option :timeout {})", timeout.as_millis()));
+    }
+}
+
+impl Drop for SmtLibSolver {
+    fn drop(&mut self) {
+        let _ = writeln!(self.stdin, "(exit)");
+        let _ = self.stdin.flush();
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
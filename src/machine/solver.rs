@@ -0,0 +1,160 @@
+use std::time::Duration;
+
+#[cfg(feature = "z3")]
+use z3::{Context, Params, Solver};
+
+#[cfg(feature = "z3")]
+use crate::expr::lower::Lowering;
+use crate::expr::model::Model;
+#[cfg(feature = "z3")]
+use crate::expr::smtlib::{self, SExpr};
+use crate::expr::Expr;
+
+// Outcome of a satisfiability check
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SatResult {
+    Sat,
+    Unsat,
+    Unknown,
+}
+
+// A decision procedure for path constraints. `BaseMachine` queries one backend for every
+// path of a run; see `BaseMachine::with_solver`.
+pub trait SolverBackend {
+    // Checks whether the conjunction of `constraints` is satisfiable. Errors with the reason
+    // when the solver could not be asked at all, e.g. a solver process that exited.
+    fn check_path(&mut self, constraints: &[Expr]) -> Result<SatResult, String>;
+
+    // Model of the last satisfiable `check_path`
+    fn get_model(&self) -> Option<Model>;
+
+    // Why the last `check_path` returned `SatResult::Unknown`
+    fn reason_unknown(&self) -> String;
+
+    // Number of solver queries made so far
+    fn queries(&self) -> usize;
+
+    // Queries running longer than `timeout` return `SatResult::Unknown`
+    fn set_timeout(&mut self, timeout: Duration);
+
+    // Values of the Int `value` in `[0, upper)` consistent with `constraints`, up to `max` of
+    // them, found by repeatedly solving and excluding the previous answer. Errors with the
+    // solver's reason if a query fails or comes back unknown.
+    fn enumerate_values(
+        &mut self,
+        constraints: &[Expr],
+        value: &Expr,
+        upper: u64,
+        max: usize,
+    ) -> Result<Vec<u64>, String> {
+        let mut query = constraints.to_vec();
+        query.push(value.ge(&Expr::int(0)));
+        query.push(value.lt(&Expr::int(upper)));
+
+        let mut values = vec![];
+        while values.len() < max {
+            match self.check_path(&query)? {
+                SatResult::Sat => {
+                    let found = self
+                        .get_model()
+                        .and_then(|model| model.eval(value).as_u64())
+                        .ok_or_else(|| "no model for a satisfiable query".to_string())?;
+                    values.push(found);
+                    query.push(value._eq(&Expr::int(found)).not());
+                }
+                SatResult::Unsat => return Ok(values),
+                SatResult::Unknown => return Err(self.reason_unknown()),
+            }
+        }
+        Ok(values)
+    }
+}
+
+// The default backend: a single z3 solver shared by every path of an exploration. Each path constraint is
+// asserted in its own scope, so moving between paths only pops back to the longest
+// common prefix of constraints instead of re-asserting the whole path.
+#[cfg(feature = "z3")]
+pub struct IncrementalSolver<'a> {
+    solver: Solver<'a>,
+    lowering: Lowering<'a>,
+    asserted: Vec<Expr>,
+    queries: usize,
+}
+
+#[cfg(feature = "z3")]
+impl<'a> IncrementalSolver<'a> {
+    pub fn new(ctx: &'a Context) -> Self {
+        Self {
+            solver: Solver::new(ctx),
+            lowering: Lowering::new(ctx),
+            asserted: vec![],
+            queries: 0,
+        }
+    }
+
+    fn sync(&mut self, constraints: &[Expr]) {
+        let shared = self
+            .asserted
+            .iter()
+            .zip(constraints)
+            .take_while(|(asserted, constraint)| asserted == constraint)
+            .count();
+
+        let stale = self.asserted.len() - shared;
+        if stale > 0 {
+            self.solver.pop(stale as u32);
+            self.asserted.truncate(shared);
+        }
+
+        for constraint in &constraints[shared..] {
+            let lowered = self.lowering.lower(constraint);
+            self.solver.push();
+            self.solver
+                .assert(&lowered.as_bool().expect("Bool constraint"));
+            self.asserted.push(constraint.clone());
+        }
+    }
+}
+
+#[cfg(feature = "z3")]
+impl SolverBackend for IncrementalSolver<'_> {
+    fn check_path(&mut self, constraints: &[Expr]) -> Result<SatResult, String> {
+        self.sync(constraints);
+        self.queries += 1;
+        Ok(match self.solver.check() {
+            z3::SatResult::Sat => SatResult::Sat,
+            z3::SatResult::Unsat => SatResult::Unsat,
+            z3::SatResult::Unknown => SatResult::Unknown,
+        })
+    }
+
+    // Evaluates the terms `Model::queries` asks for in z3's model and reads the values back
+    // from their SMT-LIB2 form
+    fn get_model(&self) -> Option<Model> {
+        let model = self.solver.get_model()?;
+        let queries = Model::queries(&self.asserted);
+        let mut lowering = Lowering::new(self.solver.get_context());
+        let values = queries
+            .iter()
+            .map(|query| {
+                let value = model.eval(&lowering.lower(query), true)?;
+                smtlib::value(&SExpr::parse(&format!("{value}\n"))?, query.sort())
+            })
+            .collect::<Option<Vec<_>>>()?;
+        Some(Model::from_values(&queries, &values))
+    }
+
+    fn reason_unknown(&self) -> String {
+        self.solver.get_reason_unknown().unwrap_or_default()
+    }
+
+    fn queries(&self) -> usize {
+        self.queries
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) {
+        let mut params = Params::new(self.solver.get_context());
+        params.set_u32("timeout", timeout.as_millis().min(u32::MAX as u128) as u32);
+        self.solver.set_params(&params);
+    }
+}
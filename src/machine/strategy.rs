@@ -0,0 +1,210 @@
+use std::collections::{HashMap, VecDeque};
+
+use super::Branch;
+
+// Decides the order in which pending branches are explored by `BaseMachine::run_sym`
+pub trait SearchStrategy<S, M> {
+    fn push(&mut self, branch: Branch<S, M>);
+    fn pop(&mut self) -> Option<Branch<S, M>>;
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+// Explores the most recently forked branch first; the default strategy
+pub struct DepthFirst<S, M> {
+    pending: Vec<Branch<S, M>>,
+}
+
+impl<S, M> DepthFirst<S, M> {
+    pub fn new() -> Self {
+        Self { pending: vec![] }
+    }
+}
+
+impl<S, M> Default for DepthFirst<S, M> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S, M> SearchStrategy<S, M> for DepthFirst<S, M> {
+    fn push(&mut self, branch: Branch<S, M>) {
+        self.pending.push(branch);
+    }
+
+    fn pop(&mut self) -> Option<Branch<S, M>> {
+        self.pending.pop()
+    }
+
+    fn len(&self) -> usize {
+        self.pending.len()
+    }
+}
+
+// Explores branches in the order they were forked
+pub struct BreadthFirst<S, M> {
+    pending: VecDeque<Branch<S, M>>,
+}
+
+impl<S, M> BreadthFirst<S, M> {
+    pub fn new() -> Self {
+        Self {
+            pending: VecDeque::new(),
+        }
+    }
+}
+
+impl<S, M> Default for BreadthFirst<S, M> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S, M> SearchStrategy<S, M> for BreadthFirst<S, M> {
+    fn push(&mut self, branch: Branch<S, M>) {
+        self.pending.push_back(branch);
+    }
+
+    fn pop(&mut self) -> Option<Branch<S, M>> {
+        self.pending.pop_front()
+    }
+
+    fn len(&self) -> usize {
+        self.pending.len()
+    }
+}
+
+// Picks a pending branch uniformly at random. The same seed always yields the same
+// exploration order.
+pub struct RandomSearch<S, M> {
+    pending: Vec<Branch<S, M>>,
+    state: u64,
+}
+
+impl<S, M> RandomSearch<S, M> {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            pending: vec![],
+            // xorshift gets stuck on a zero state
+            state: seed.max(1),
+        }
+    }
+
+    fn next_rand(&mut self) -> u64 {
+        // xorshift64
+        let mut x = self.state;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.state = x;
+        x
+    }
+}
+
+impl<S, M> SearchStrategy<S, M> for RandomSearch<S, M> {
+    fn push(&mut self, branch: Branch<S, M>) {
+        self.pending.push(branch);
+    }
+
+    fn pop(&mut self) -> Option<Branch<S, M>> {
+        if self.pending.is_empty() {
+            return None;
+        }
+        let idx = (self.next_rand() % self.pending.len() as u64) as usize;
+        Some(self.pending.swap_remove(idx))
+    }
+
+    fn len(&self) -> usize {
+        self.pending.len()
+    }
+}
+
+// Prefers branches whose pc has been visited the fewest times, steering exploration
+// towards uncovered code. Ties go to the most recently forked branch.
+pub struct CoverageGuided<S, M> {
+    pending: Vec<Branch<S, M>>,
+    visits: HashMap<usize, usize>,
+}
+
+impl<S, M> CoverageGuided<S, M> {
+    pub fn new() -> Self {
+        Self {
+            pending: vec![],
+            visits: HashMap::new(),
+        }
+    }
+
+    pub fn visits(&self, pc: usize) -> usize {
+        self.visits.get(&pc).copied().unwrap_or(0)
+    }
+}
+
+impl<S, M> Default for CoverageGuided<S, M> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S, M> SearchStrategy<S, M> for CoverageGuided<S, M> {
+    fn push(&mut self, branch: Branch<S, M>) {
+        self.pending.push(branch);
+    }
+
+    fn pop(&mut self) -> Option<Branch<S, M>> {
+        let idx = self
+            .pending
+            .iter()
+            .enumerate()
+            .rev()
+            .min_by_key(|(_, b)| self.visits(b.pc))
+            .map(|(idx, _)| idx)?;
+        let branch = self.pending.remove(idx);
+        *self.visits.entry(branch.pc).or_insert(0) += 1;
+        Some(branch)
+    }
+
+    fn len(&self) -> usize {
+        self.pending.len()
+    }
+}
+
+// Explores the branch that has executed the fewest instructions first. Ties go to the
+// earliest forked branch.
+pub struct ShortestPathFirst<S, M> {
+    pending: Vec<Branch<S, M>>,
+}
+
+impl<S, M> ShortestPathFirst<S, M> {
+    pub fn new() -> Self {
+        Self { pending: vec![] }
+    }
+}
+
+impl<S, M> Default for ShortestPathFirst<S, M> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S, M> SearchStrategy<S, M> for ShortestPathFirst<S, M> {
+    fn push(&mut self, branch: Branch<S, M>) {
+        self.pending.push(branch);
+    }
+
+    fn pop(&mut self) -> Option<Branch<S, M>> {
+        let idx = self
+            .pending
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, b)| b.steps)
+            .map(|(idx, _)| idx)?;
+        Some(self.pending.remove(idx))
+    }
+
+    fn len(&self) -> usize {
+        self.pending.len()
+    }
+}
@@ -0,0 +1,177 @@
+use std::collections::HashSet;
+use std::fmt;
+
+use crate::expr::model::Model;
+use crate::expr::{Expr, Op, Sort};
+use crate::memory::SymbolicMem;
+
+// Hands out unique symbol names under a prefix and remembers the inputs and memories they
+// stand for, so that a model can be read back in terms of them. Give each machine whose paths
+// share a solver its own prefix.
+#[derive(Debug)]
+pub struct SymbolRegistry {
+    prefix: String,
+    issued: HashSet<String>,
+    symbols: Vec<Symbol>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Symbol {
+    // The name as requested, made unique within the registry
+    pub label: String,
+    pub kind: SymbolKind,
+    pub term: Expr,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolKind {
+    Input,
+    // An array holding the initial contents of a memory
+    Memory,
+}
+
+// The value a model gives to an input, or to one initial memory cell read on the path
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Binding {
+    pub label: String,
+    pub index: Option<String>,
+    pub value: String,
+}
+
+impl fmt::Display for Binding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.index {
+            Some(index) => write!(f, "{}[{index}] = {}", self.label, self.value),
+            None => write!(f, "{} = {}", self.label, self.value),
+        }
+    }
+}
+
+impl SymbolRegistry {
+    // An empty prefix leaves names as they are requested
+    pub fn new(prefix: impl Into<String>) -> Self {
+        Self {
+            prefix: prefix.into(),
+            issued: HashSet::new(),
+            symbols: vec![],
+        }
+    }
+
+    pub fn symbols(&self) -> &[Symbol] {
+        &self.symbols
+    }
+
+    pub fn lookup(&self, label: &str) -> Option<&Symbol> {
+        self.symbols.iter().find(|symbol| symbol.label == label)
+    }
+
+    // A name no earlier call returned: `prefix.base`, then `prefix.base.1` and so on
+    pub fn fresh_name(&mut self, base: &str) -> String {
+        self.fresh(base).1
+    }
+
+    pub fn int(&mut self, base: &str) -> Expr {
+        self.input(base, Sort::Int)
+    }
+
+    pub fn bool(&mut self, base: &str) -> Expr {
+        self.input(base, Sort::Bool)
+    }
+
+    pub fn bv(&mut self, base: &str, width: u32) -> Expr {
+        self.input(base, Sort::BitVec(width))
+    }
+
+    // Builds a memory from a fresh name, e.g.
+    // `|name| BaseMemorySymbolicArray::named(name, Sort::Int, Sort::Int)`, and registers the
+    // arrays among its terms
+    pub fn memory<M: SymbolicMem>(&mut self, base: &str, make: impl FnOnce(&str) -> M) -> M {
+        let (label, name) = self.fresh(base);
+        let mem = make(&name);
+        for term in mem.terms() {
+            if let Sort::Array(..) = term.sort() {
+                self.register(label.clone(), SymbolKind::Memory, term);
+            }
+        }
+        mem
+    }
+
+    // The value of every input in `model`, followed by the initial memory cells `constraints`
+    // read
+    pub fn decode(&self, model: &Model, constraints: &[Expr]) -> Vec<Binding> {
+        let mut bindings = vec![];
+        for symbol in &self.symbols {
+            if symbol.kind == SymbolKind::Input {
+                bindings.push(Binding {
+                    label: symbol.label.clone(),
+                    index: None,
+                    value: model.eval(&symbol.term).to_string(),
+                });
+            }
+        }
+
+        for constraint in constraints {
+            constraint.visit(|term| {
+                if let (Op::Select, [array, index]) = (term.op(), term.args()) {
+                    if let Some(binding) = self.decode_read(model, array, index) {
+                        if !bindings.contains(&binding) {
+                            bindings.push(binding);
+                        }
+                    }
+                }
+            });
+        }
+        bindings
+    }
+
+    // The initial value of the cell read by `select(array, index)`, when `array` is a
+    // registered memory with some stores on top
+    fn decode_read(&self, model: &Model, array: &Expr, index: &Expr) -> Option<Binding> {
+        let mut base = array;
+        while let Op::Store = base.op() {
+            base = &base.args()[0];
+        }
+        let symbol = self
+            .symbols
+            .iter()
+            .find(|symbol| symbol.kind == SymbolKind::Memory && symbol.term == *base)?;
+        let index = model.eval(index);
+        let value = model.eval(&base.select(&index));
+        Some(Binding {
+            label: symbol.label.clone(),
+            index: Some(index.to_string()),
+            value: value.to_string(),
+        })
+    }
+
+    fn input(&mut self, base: &str, sort: Sort) -> Expr {
+        let (label, name) = self.fresh(base);
+        let input = Expr::var(name, sort);
+        self.register(label, SymbolKind::Input, input.clone());
+        input
+    }
+
+    // The unique label for `base` and its name with the prefix
+    fn fresh(&mut self, base: &str) -> (String, String) {
+        let qualified = |label: &str| {
+            if self.prefix.is_empty() {
+                label.to_string()
+            } else {
+                format!("{}.{label}", self.prefix)
+            }
+        };
+        let mut label = base.to_string();
+        let mut n = 0;
+        while self.issued.contains(&qualified(&label)) {
+            n += 1;
+            label = format!("{base}.{n}");
+        }
+        let name = qualified(&label);
+        self.issued.insert(name.clone());
+        (label, name)
+    }
+
+    fn register(&mut self, label: String, kind: SymbolKind, term: Expr) {
+        self.symbols.push(Symbol { label, kind, term });
+    }
+}
@@ -0,0 +1,137 @@
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+
+use crate::expr::{Expr, Sort};
+
+use super::error::MemoryError;
+use super::{MemoryResult, ReadOnlyMem, WriteableMem};
+
+// Byte order of multi-byte accesses
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Endian {
+    // The byte at the lowest address is the most significant, as on the EVM
+    Big,
+    // The byte at the lowest address is the least significant, as on WASM
+    Little,
+}
+
+// Multi-byte access to a memory of 8 bit cells with bitvector addresses, such as a
+// `BaseMemorySymbolicArray` over those sorts or `BaseMemoryBitVecIndex`. Accesses need not be
+// aligned, and addresses wrap around at the end of the index sort.
+pub trait ByteMemory:
+    ReadOnlyMem<Index = Expr, MemVal = Expr> + WriteableMem<Index = Expr, MemVal = Expr> + Clone
+{
+    // Reads `width` bytes starting at `offset` as one bitvector of 8 * `width` bits
+    fn load(&self, offset: &Expr, width: usize, endian: Endian) -> MemoryResult<Expr> {
+        if width == 0 {
+            return Err(MemoryError::ValueNotSupported(
+                "load of zero bytes".to_string(),
+            ));
+        }
+        let mut bytes = (0..width)
+            .map(|i| read_byte(self, &address(offset, i)?))
+            .collect::<MemoryResult<Vec<_>>>()?;
+        if endian == Endian::Little {
+            bytes.reverse();
+        }
+        let (first, rest) = bytes.split_first().expect("at least one byte");
+        Ok(rest
+            .iter()
+            .fold(first.clone(), |high, low| high.concat(low)))
+    }
+
+    // Writes the bytes of `val`, whose width must be a whole number of bytes, from `offset` on
+    fn store(&self, offset: &Expr, val: &Expr, endian: Endian) -> MemoryResult<Self> {
+        let bits = match val.sort() {
+            Sort::BitVec(bits) if bits.is_multiple_of(8) => *bits,
+            Sort::BitVec(bits) => {
+                return Err(MemoryError::ValueNotSupported(format!(
+                    "store of {bits} bits, which is not a whole number of bytes"
+                )))
+            }
+            sort => {
+                return Err(MemoryError::ValueNotSupported(format!(
+                    "store of a value of sort {sort}"
+                )))
+            }
+        };
+        check_cells(self, offset)?;
+        let width = (bits / 8) as usize;
+        (0..width).try_fold(self.clone(), |mem, i| {
+            // Which byte of the value goes to `offset + i`, counting from the least significant
+            let significance = match endian {
+                Endian::Big => width - 1 - i,
+                Endian::Little => i,
+            } as u32;
+            let byte = val.extract(8 * significance + 7, 8 * significance);
+            mem.write(address(offset, i)?, byte)
+        })
+    }
+
+    // Stores a concrete value of 8 bytes
+    fn store_u64(&self, offset: &Expr, val: u64, endian: Endian) -> MemoryResult<Self> {
+        let mut buf = [0; 8];
+        match endian {
+            Endian::Big => BigEndian::write_u64(&mut buf, val),
+            Endian::Little => LittleEndian::write_u64(&mut buf, val),
+        }
+        self.store_bytes(offset, &buf)
+    }
+
+    // Stores concrete bytes in order, e.g. a program's code or input
+    fn store_bytes(&self, offset: &Expr, bytes: &[u8]) -> MemoryResult<Self> {
+        check_cells(self, offset)?;
+        bytes
+            .iter()
+            .enumerate()
+            .try_fold(self.clone(), |mem, (i, byte)| {
+                mem.write(address(offset, i)?, Expr::bv(*byte as u64, 8))
+            })
+    }
+}
+
+impl<M> ByteMemory for M where
+    M: ReadOnlyMem<Index = Expr, MemVal = Expr> + WriteableMem<Index = Expr, MemVal = Expr> + Clone
+{
+}
+
+fn read_byte(
+    mem: &impl ReadOnlyMem<Index = Expr, MemVal = Expr>,
+    address: &Expr,
+) -> MemoryResult<Expr> {
+    match mem.read(address.clone())? {
+        Some(byte) if *byte.sort() == Sort::BitVec(8) => Ok(byte),
+        Some(cell) => match cell.sort() {
+            Sort::BitVec(width) => Err(MemoryError::ValueNotSupported(format!(
+                "byte access to a memory of {width} bit cells"
+            ))),
+            _ => Err(MemoryError::ValueNotSupported(
+                "memory cells are not bitvectors".to_string(),
+            )),
+        },
+        None => Err(MemoryError::ValueNotSupported(
+            "memory cells are not bitvectors".to_string(),
+        )),
+    }
+}
+
+// Stores are checked up front so that a memory of wider cells fails as loads do, rather than
+// with a sort mismatch when writing a byte
+fn check_cells(
+    mem: &impl ReadOnlyMem<Index = Expr, MemVal = Expr>,
+    offset: &Expr,
+) -> MemoryResult<()> {
+    read_byte(mem, &address(offset, 0)?).map(|_| ())
+}
+
+fn address(offset: &Expr, i: usize) -> MemoryResult<Expr> {
+    let Sort::BitVec(width) = *offset.sort() else {
+        return Err(MemoryError::ValueNotSupported(format!(
+            "byte access at an address of sort {}",
+            offset.sort()
+        )));
+    };
+    if i == 0 {
+        return Ok(offset.clone());
+    }
+    Ok(offset.add(&Expr::bv(i as u64, width)))
+}
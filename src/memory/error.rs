@@ -1,9 +1,13 @@
 use thiserror::{self, Error};
 
-#[derive(Debug, Error)]
+use crate::expr::error::ExprError;
+
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
 pub enum MemoryError {
     #[error("Invalid Index {0}")]
     InvalidIndex(usize),
     #[error("Value not supported {0}")]
     ValueNotSupported(String),
+    #[error(transparent)]
+    Expr(#[from] ExprError),
 }
@@ -1,130 +1,304 @@
-use crate::instructions::val::{HybridVal, SymbolicBytes, Val};
+use crate::expr::{Expr, Op, Sort};
+use crate::instructions::val::Val;
+use std::collections::HashSet;
 use std::marker::PhantomData;
 use std::rc::Rc;
-use z3::ast::{Array, Ast, Int, BV};
-use z3::{Context, FuncDecl};
 
-use super::{RWMem, ReadOnlyMem, WriteableMem};
+use super::error::MemoryError;
+use super::{MemoryResult, RWMem, ReadOnlyMem, SymbolicMem, WriteableMem};
+// A fixed number of cells addressed by concrete indices. Every cell starts out holding the
+// default value given at initialisation. Cells live in shared pages that are copied on
+// write, so cloning the memory is O(1) and a write copies one page and the page table.
 #[derive(Debug, Clone)]
 pub struct BaseMemoryConcreteIndex<T> {
-    pub _inner: Vec<Val<T>>,
+    pub(crate) pages: Rc<Vec<Rc<Vec<Val<T>>>>>,
+    // The page all pages start out as; pages still sharing it were never written
+    pub(crate) blank: Rc<Vec<Val<T>>>,
+    pub(crate) size: usize,
     pub(crate) idx_set: PhantomData<usize>,
     pub(crate) val_set: PhantomData<Val<T>>,
 }
+
+// Cells per page of `BaseMemoryConcreteIndex`
+const PAGE_SIZE: usize = 256;
+// An array over the index and value sorts it was created with. Reads and writes with
+// indices or values of other sorts fail with `MemoryError::Expr`.
 #[derive(Debug, Clone)]
-pub struct BaseMemorySymbolicArray<'a, I, T> {
-    pub _inner: Array<'a>,
-    pub(crate) idx_set: PhantomData<I>,
-    pub(crate) val_set: PhantomData<Val<T>>,
+pub struct BaseMemorySymbolicArray {
+    pub _inner: Expr,
 }
-#[derive(Debug)]
-pub struct BaseMemorySymbolicUF<'a, I, T> {
-    pub _inner: FuncDecl<'a>,
-    pub(crate) idx_set: PhantomData<I>,
-    pub(crate) val_set: PhantomData<Val<T>>,
+// Reads apply an uninterpreted function to the index, which suits read-only data such as code
+// or inputs. Writes are logged rather than applied; reads see them through an if-then-else
+// chain, newest first.
+#[derive(Debug, Clone)]
+pub struct BaseMemorySymbolicUF {
+    pub(crate) name: String,
+    pub(crate) domain: Sort,
+    pub(crate) range: Sort,
+    pub(crate) writes: Vec<(Expr, Expr)>,
 }
 
-// pub struct BaseMemoryBitVecIndex<'a, T> {
-//     pub _inner: Array<'a>,
-//     pub(crate) idx_set: PhantomData<BV<'a>>,
-//     pub(crate) val_set: PhantomData<Val<T>>,
-// }
+// An array indexed by fixed-width bitvectors, so address arithmetic wraps around like it
+// does on real machines
+#[derive(Debug, Clone)]
+pub struct BaseMemoryBitVecIndex {
+    pub _inner: BaseMemorySymbolicArray,
+    pub(crate) address_width: u32,
+    pub(crate) value_width: u32,
+}
 
-pub type MemConcreteToBitVec<'a> = BaseMemoryConcreteIndex<BV<'a>>;
-pub type MemConcreteToInt<'a> = BaseMemoryConcreteIndex<Int<'a>>;
-pub type MemBitVecToBitVec<'a> = BaseMemorySymbolicArray<'a, BV<'a>, BV<'a>>;
-pub type MemIntToInt<'a> = BaseMemorySymbolicArray<'a, Int<'a>, Int<'a>>;
-pub type MemBitVecToAny<'a, T> = BaseMemorySymbolicArray<'a, BV<'a>, T>;
-pub type MemBitVecToInt<'a> = MemBitVecToAny<'a, Int<'a>>;
+impl<T> BaseMemoryConcreteIndex<T> {
+    pub fn size(&self) -> usize {
+        self.size
+    }
 
-impl<'a, I> ReadOnlyMem for BaseMemorySymbolicArray<'a, I, BV<'a>>
+    // Every cell in index order
+    pub fn cells(&self) -> impl Iterator<Item = &T> {
+        self.pages
+            .iter()
+            .flat_map(|page| page.iter().map(|val| &val.0))
+            .take(self.size)
+    }
+
+    // Cells of the pages that have been written to
+    fn written_cells(&self) -> impl Iterator<Item = &T> {
+        self.pages
+            .iter()
+            .enumerate()
+            .filter(|(_, page)| !Rc::ptr_eq(page, &self.blank))
+            .flat_map(|(n, page)| {
+                let len = (self.size - n * PAGE_SIZE).min(PAGE_SIZE);
+                page[..len].iter().map(|val| &val.0)
+            })
+    }
+}
+
+impl<T> ReadOnlyMem for BaseMemoryConcreteIndex<T>
 where
-    I: z3::ast::Ast<'a>,
+    T: Clone,
 {
-    type MemVal = BV<'a>;
+    type MemVal = T;
 
-    type Index = I;
+    type Index = usize;
 
     fn read(&self, idx: Self::Index) -> super::MemoryResult<Option<Self::MemVal>> {
-        Ok(self._inner.select(&idx).as_bv())
+        if idx >= self.size {
+            return Err(MemoryError::InvalidIndex(idx));
+        }
+        Ok(Some(self.pages[idx / PAGE_SIZE][idx % PAGE_SIZE].0.clone()))
     }
 }
 
-impl<'a, I> WriteableMem for BaseMemorySymbolicArray<'a, I, BV<'a>>
+impl<T> WriteableMem for BaseMemoryConcreteIndex<T>
 where
-    I: z3::ast::Ast<'a>,
+    T: Clone,
 {
-    type MemVal = BV<'a>;
+    type MemVal = T;
 
-    type Index = I;
+    type Index = usize;
 
     fn write(&self, idx: Self::Index, val: Self::MemVal) -> super::MemoryResult<Self> {
+        if idx >= self.size {
+            return Err(MemoryError::InvalidIndex(idx));
+        }
+        let mut pages = self.pages.clone();
+        let page = Rc::make_mut(&mut Rc::make_mut(&mut pages)[idx / PAGE_SIZE]);
+        page[idx % PAGE_SIZE] = Val(val);
+        Ok(Self {
+            pages,
+            blank: self.blank.clone(),
+            size: self.size,
+            idx_set: PhantomData::<usize>,
+            val_set: PhantomData::<Val<T>>,
+        })
+    }
+}
+
+impl<T> RWMem for BaseMemoryConcreteIndex<T>
+where
+    T: Clone,
+{
+    // Number of cells and the value of uninitialised cells
+    type InitArgs = (usize, T);
+
+    fn init(args: Self::InitArgs) -> MemoryResult<Self> {
+        let (size, default) = args;
+        // Pages all start out as the same page. The last one is padded rather than cut short,
+        // so that it is shared too.
+        let blank = Rc::new(vec![Val(default); PAGE_SIZE]);
+        let pages = vec![blank.clone(); size.div_ceil(PAGE_SIZE)];
         Ok(Self {
-            _inner: self._inner.store(&idx, &val),
-            idx_set: PhantomData::<I>,
-            val_set: PhantomData::<Val<BV<'a>>>,
+            pages: Rc::new(pages),
+            blank,
+            size,
+            idx_set: PhantomData::<usize>,
+            val_set: PhantomData::<Val<T>>,
         })
     }
 }
 
-impl<'a, I> RWMem for BaseMemorySymbolicArray<'a, I, BV<'a>>
+// Stored values may contain symbolic inputs. Constants contain none, and unwritten cells all
+// hold the default value, so only the written pages are looked at. Each term is listed once.
+impl<T> SymbolicMem for BaseMemoryConcreteIndex<T>
 where
-    I: z3::ast::Ast<'a> + Clone,
+    T: Into<Expr> + Clone,
 {
-    type InitArgs = (Rc<&'a Context>, I, usize);
+    fn terms(&self) -> Vec<Expr> {
+        let unwritten = self.pages.iter().any(|page| Rc::ptr_eq(page, &self.blank));
+        let default = unwritten.then(|| &self.blank[0].0);
+        let mut seen = HashSet::new();
+        default
+            .into_iter()
+            .chain(self.written_cells())
+            .map(|val| val.clone().into())
+            .filter(|term: &Expr| !term.is_constant() && seen.insert(term.clone()))
+            .collect()
+    }
+}
 
-    fn init(args: Self::InitArgs) -> Self {
-        let (ctx, domain, range_size) = args;
+impl BaseMemorySymbolicUF {
+    // Applications of the function `name` from `domain` to `range`
+    pub fn new(name: impl Into<String>, domain: Sort, range: Sort) -> Self {
         Self {
-            _inner: Array::new_const(
-                ctx.as_ref(),
-                "memory",
-                &I::get_sort(&domain),
-                &z3::Sort::bitvector(ctx.as_ref(), range_size as u32),
-            ),
-            idx_set: PhantomData::<I>,
-            val_set: PhantomData::<Val<BV<'a>>>,
+            name: name.into(),
+            domain,
+            range,
+            writes: vec![],
         }
     }
+
+    // Writes in the order they were made
+    pub fn writes(&self) -> &[(Expr, Expr)] {
+        &self.writes
+    }
+
+    fn check(&self, idx: &Expr, val: Option<&Expr>) -> MemoryResult<()> {
+        let sorts = [Some(idx.sort()), val.map(Expr::sort)];
+        if sorts[0] != Some(&self.domain) || sorts[1].is_some_and(|sort| *sort != self.range) {
+            return Err(MemoryError::ValueNotSupported(format!(
+                "{} maps {} to {}",
+                self.name, self.domain, self.range
+            )));
+        }
+        Ok(())
+    }
 }
 
-impl<'a> ReadOnlyMem for MemIntToInt<'a> {
-    type MemVal = Int<'a>;
+impl ReadOnlyMem for BaseMemorySymbolicUF {
+    type MemVal = Expr;
 
-    type Index = Int<'a>;
+    type Index = Expr;
 
-    fn read(&self, idx: Self::Index) -> super::MemoryResult<Option<Self::MemVal>> {
-        Ok(self._inner.select(&idx).as_int())
+    fn read(&self, idx: Self::Index) -> MemoryResult<Option<Self::MemVal>> {
+        self.check(&idx, None)?;
+        let unwritten = Expr::call(
+            self.name.clone(),
+            std::slice::from_ref(&idx),
+            self.range.clone(),
+        );
+        let read = self.writes.iter().fold(unwritten, |older, (written, val)| {
+            let hit = idx._eq(written).simplify();
+            match hit.as_bool() {
+                Some(true) => val.clone(),
+                Some(false) => older,
+                None => hit.ite(val, &older),
+            }
+        });
+        Ok(Some(read))
     }
 }
 
-impl<'a> WriteableMem for MemIntToInt<'a> {
-    type MemVal = Int<'a>;
+impl WriteableMem for BaseMemorySymbolicUF {
+    type MemVal = Expr;
 
-    type Index = Int<'a>;
+    type Index = Expr;
 
-    fn write(&self, idx: Self::Index, val: Self::MemVal) -> super::MemoryResult<Self> {
+    fn write(&self, idx: Self::Index, val: Self::MemVal) -> MemoryResult<Self> {
+        self.check(&idx, Some(&val))?;
+        let mut writes = self.writes.clone();
+        writes.push((idx, val));
+        Ok(Self {
+            writes,
+            ..self.clone()
+        })
+    }
+}
+
+impl RWMem for BaseMemorySymbolicUF {
+    // The function's name, domain and range
+    type InitArgs = (String, Sort, Sort);
+
+    fn init(args: Self::InitArgs) -> MemoryResult<Self> {
+        let (name, domain, range) = args;
+        domain.check()?;
+        range.check()?;
+        Ok(Self::new(name, domain, range))
+    }
+}
+
+// Reads of the function show up in the path constraints themselves
+impl SymbolicMem for BaseMemorySymbolicUF {
+    fn terms(&self) -> Vec<Expr> {
+        self.writes
+            .iter()
+            .flat_map(|(idx, val)| [idx.clone(), val.clone()])
+            .collect()
+    }
+}
+
+impl SymbolicMem for BaseMemorySymbolicArray {
+    fn terms(&self) -> Vec<Expr> {
+        vec![self._inner.clone()]
+    }
+}
+
+impl ReadOnlyMem for BaseMemorySymbolicArray {
+    type MemVal = Expr;
+
+    type Index = Expr;
+
+    fn read(&self, idx: Self::Index) -> MemoryResult<Option<Self::MemVal>> {
+        Ok(Some(Expr::app(Op::Select, vec![self._inner.clone(), idx])?))
+    }
+}
+
+impl WriteableMem for BaseMemorySymbolicArray {
+    type MemVal = Expr;
+
+    type Index = Expr;
+
+    fn write(&self, idx: Self::Index, val: Self::MemVal) -> MemoryResult<Self> {
         Ok(Self {
-            _inner: self._inner.store(&idx, &val),
-            idx_set: PhantomData::<Int<'a>>,
-            val_set: PhantomData::<Val<Int<'a>>>,
+            _inner: Expr::app(Op::Store, vec![self._inner.clone(), idx, val])?,
         })
     }
 }
 
-impl<'a> RWMem for MemIntToInt<'a> {
-    type InitArgs = Rc<&'a Context>;
+impl RWMem for BaseMemorySymbolicArray {
+    // Index and value sorts. Each memory gets a fresh name, so memories made this way never
+    // alias.
+    type InitArgs = (Sort, Sort);
+
+    fn init(args: Self::InitArgs) -> MemoryResult<Self> {
+        let (index, value) = args;
+        index.check()?;
+        value.check()?;
+        Ok(Self::fresh("memory", index, value))
+    }
+}
+
+impl BaseMemorySymbolicArray {
+    // A memory whose initial contents are the array variable `name`
+    pub fn named(name: impl Into<String>, index: Sort, value: Sort) -> Self {
+        Self {
+            _inner: Expr::var(name, Sort::array(index, value)),
+        }
+    }
 
-    fn init(args: Self::InitArgs) -> Self {
+    // Like `named`, with a name no other fresh variable has, see `Expr::fresh_var`
+    pub fn fresh(prefix: &str, index: Sort, value: Sort) -> Self {
         Self {
-            _inner: Array::new_const(
-                args.as_ref(),
-                "memory",
-                &z3::Sort::int(&args),
-                &z3::Sort::int(&args),
-            ),
-            idx_set: PhantomData::<Int<'a>>,
-            val_set: PhantomData::<Val<Int<'a>>>,
+            _inner: Expr::fresh_var(prefix, Sort::array(index, value)),
         }
     }
 }
@@ -1,12 +1,11 @@
+pub mod bytes;
 pub mod error;
 pub mod memory_models;
 pub mod symbolic;
 pub mod symbolic_bv;
-use std::marker::PhantomData;
-
-use crate::instructions::val::Val;
 use error::MemoryError;
-use z3::{ast::Array, FuncDecl};
+
+use crate::expr::Expr;
 
 pub type MemoryResult<T> = Result<T, MemoryError>;
 pub trait ReadOnlyMem: Sized {
@@ -24,13 +23,22 @@ pub trait WriteableMem: Sized {
 
 pub trait RWMem: ReadOnlyMem + WriteableMem {
     type InitArgs: Clone;
-    fn init(args: Self::InitArgs) -> Self;
+    // Fails when the arguments describe no memory, e.g. a zero-width bitvector sort
+    fn init(args: Self::InitArgs) -> MemoryResult<Self>;
+}
+
+// Memories built from symbolic expressions. Exporting a path's constraints declares the free
+// symbols of these terms, such as the initial memory array.
+pub trait SymbolicMem {
+    fn terms(&self) -> Vec<Expr>;
 }
 
 pub type MemorySlotChange<Idx, MemVal> = (Idx, MemVal, MemVal);
+#[derive(Clone)]
 pub enum MemOpRecord<I, V> {
     Write(MemorySlotChange<I, V>),
 }
+#[derive(Clone)]
 pub struct MemRecord<M: WriteableMem> {
     pub diff: Vec<MemOpRecord<M::Index, M::MemVal>>,
 }
@@ -40,24 +48,25 @@ where
     M: WriteableMem,
 {
     pub fn apply(self, memory: M) -> MemoryResult<M> {
-        let final_mem = self.diff.into_iter().fold(
-            Ok(memory),
-            |cur_mem: MemoryResult<M>, r| -> MemoryResult<M> {
-                match cur_mem {
-                    Ok(m) => {
-                        if let MemOpRecord::Write(r) = r {
-                            let idx = r.0;
-                            let _old_val = r.1;
-                            let new_val = r.2;
-                            m.write(idx, new_val)
-                        } else {
-                            Ok(m)
-                        }
-                    }
-                    Err(e) => Err(e),
-                }
-            },
-        );
-        final_mem
+        self.diff
+            .into_iter()
+            .try_fold(memory, |m, r| -> MemoryResult<M> {
+                let MemOpRecord::Write(r) = r;
+                let idx = r.0;
+                let _old_val = r.1;
+                let new_val = r.2;
+                m.write(idx, new_val)
+            })
+    }
+
+    // Undoes the record on the memory it was applied to, restoring old values newest first
+    pub fn revert(self, memory: M) -> MemoryResult<M> {
+        self.diff
+            .into_iter()
+            .rev()
+            .try_fold(memory, |m, r| -> MemoryResult<M> {
+                let MemOpRecord::Write((idx, old_val, _new_val)) = r;
+                m.write(idx, old_val)
+            })
     }
 }
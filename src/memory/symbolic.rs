@@ -1,45 +1,54 @@
-use crate::memory::{MemoryResult, RWMem, ReadOnlyMem, WriteableMem};
-use std::rc::Rc;
-use z3::ast::{Array, Int};
-use z3::Context;
+use crate::expr::{Expr, Op, Sort};
+use crate::memory::{MemoryResult, RWMem, ReadOnlyMem, SymbolicMem, WriteableMem};
 #[derive(Clone, Debug)]
-pub struct BaseSymbolicMem<'a> {
-    inner: Array<'a>,
+pub struct BaseSymbolicMem {
+    inner: Expr,
 }
 
-impl<'a> ReadOnlyMem for BaseSymbolicMem<'a> {
-    type MemVal = Int<'a>;
+impl ReadOnlyMem for BaseSymbolicMem {
+    type MemVal = Expr;
 
-    type Index = Int<'a>;
+    type Index = Expr;
 
     fn read(&self, idx: Self::Index) -> MemoryResult<Option<Self::MemVal>> {
-        Ok(self.inner.select(&idx).as_int())
+        Ok(Some(Expr::app(Op::Select, vec![self.inner.clone(), idx])?))
     }
 }
 
-impl<'a> WriteableMem for BaseSymbolicMem<'a> {
-    type MemVal = Int<'a>;
+impl WriteableMem for BaseSymbolicMem {
+    type MemVal = Expr;
 
-    type Index = Int<'a>;
+    type Index = Expr;
 
     fn write(&self, idx: Self::Index, val: Self::MemVal) -> MemoryResult<Self> {
         Ok(Self {
-            inner: self.inner.store(&idx, &val),
+            inner: Expr::app(Op::Store, vec![self.inner.clone(), idx, val])?,
         })
     }
 }
 
-impl<'a> RWMem for BaseSymbolicMem<'a> {
-    type InitArgs = Rc<&'a Context>;
+impl SymbolicMem for BaseSymbolicMem {
+    fn terms(&self) -> Vec<Expr> {
+        vec![self.inner.clone()]
+    }
+}
+
+impl RWMem for BaseSymbolicMem {
+    type InitArgs = ();
+
+    // A fresh name, so memories made this way never alias
+    fn init(_args: Self::InitArgs) -> MemoryResult<Self> {
+        Ok(Self {
+            inner: Expr::fresh_var("memory", Sort::array(Sort::Int, Sort::Int)),
+        })
+    }
+}
 
-    fn init(args: Self::InitArgs) -> Self {
+impl BaseSymbolicMem {
+    // A memory whose initial contents are the Int array variable `name`
+    pub fn named(name: &str) -> Self {
         Self {
-            inner: Array::new_const(
-                args.as_ref(),
-                "memory",
-                &z3::Sort::int(args.as_ref()),
-                &z3::Sort::int(args.as_ref()),
-            ),
+            inner: Expr::var(name, Sort::array(Sort::Int, Sort::Int)),
         }
     }
 }
@@ -1,55 +1,112 @@
-use std::{marker::PhantomData, rc::Rc};
-
-use crate::{
-    instructions::val::Val,
-    memory::{memory_models::MemBitVecToBitVec, RWMem, ReadOnlyMem, WriteableMem},
-};
-use byteorder::{BigEndian, LittleEndian};
-use z3::{
-    ast::{Array, Ast, Bool, BV},
-    Context,
-};
-
-use super::MemoryResult;
-
-// impl<'a> ReadOnlyMem for BaseMemoryBitVecIndex<'a, BV<'a>> {
-//     type MemVal = BV<'a>;
-
-//     type Index = BV<'a>;
-
-//     fn read(&self, idx: Self::Index) -> MemoryResult<Option<Self::MemVal>> {
-//         Ok(self._inner.select(&idx).as_bv())
-//     }
-// }
-
-// impl<'a> WriteableMem for BaseMemoryBitVecIndex<'a, BV<'a>> {
-//     type MemVal = BV<'a>;
-
-//     type Index = BV<'a>;
-
-//     fn write(&self, idx: Self::Index, val: Self::MemVal) -> MemoryResult<Self> {
-//         Ok(Self {
-//             _inner: self._inner.store(&idx, &val),
-//             idx_set: PhantomData::<BV<'a>>,
-//             val_set: PhantomData::<Val<BV<'a>>>,
-//         })
-//     }
-// }
-
-// impl<'a> RWMem for BaseMemoryBitVecIndex<'a, BV<'a>> {
-//     type InitArgs = (Rc<&'a Context>, usize, usize);
-
-//     fn init(args: Self::InitArgs) -> Self {
-//         let (ctx, domain_size, range_size) = args;
-//         Self {
-//             _inner: Array::new_const(
-//                 ctx.as_ref(),
-//                 "memory",
-//                 &z3::Sort::bitvector(ctx.as_ref(), domain_size as u32),
-//                 &z3::Sort::bitvector(ctx.as_ref(), range_size as u32),
-//             ),
-//             idx_set: PhantomData::<BV<'a>>,
-//             val_set: PhantomData::<Val<BV<'a>>>,
-//         }
-//     }
-// }
+use crate::expr::{Expr, Sort};
+
+use super::error::MemoryError;
+use super::memory_models::{BaseMemoryBitVecIndex, BaseMemorySymbolicArray};
+use super::{MemoryResult, RWMem, ReadOnlyMem, SymbolicMem, WriteableMem};
+
+impl BaseMemoryBitVecIndex {
+    // A memory whose initial contents are the array variable `name`. Machines explored side by
+    // side need distinct names, or their memories are the same unknown.
+    pub fn named(
+        name: impl Into<String>,
+        address_width: u32,
+        value_width: u32,
+    ) -> MemoryResult<Self> {
+        let (index, value) = Self::sorts(address_width, value_width)?;
+        Ok(Self::from_array(
+            BaseMemorySymbolicArray::named(name, index, value),
+            address_width,
+            value_width,
+        ))
+    }
+
+    // Like `named`, with a name no other fresh variable has, see `Expr::fresh_var`
+    pub fn fresh(prefix: &str, address_width: u32, value_width: u32) -> MemoryResult<Self> {
+        let (index, value) = Self::sorts(address_width, value_width)?;
+        Ok(Self::from_array(
+            BaseMemorySymbolicArray::fresh(prefix, index, value),
+            address_width,
+            value_width,
+        ))
+    }
+
+    // Every cell holds `val`, which must be a bitvector of the value width
+    pub fn filled(address_width: u32, val: &Expr) -> MemoryResult<Self> {
+        let Sort::BitVec(value_width) = *val.sort() else {
+            return Err(MemoryError::ValueNotSupported(format!(
+                "memory cells must be bitvectors, not {}",
+                val.sort()
+            )));
+        };
+        let (index, _) = Self::sorts(address_width, value_width)?;
+        let array = BaseMemorySymbolicArray {
+            _inner: Expr::const_array(index, val),
+        };
+        Ok(Self::from_array(array, address_width, value_width))
+    }
+
+    pub fn address_width(&self) -> u32 {
+        self.address_width
+    }
+
+    pub fn value_width(&self) -> u32 {
+        self.value_width
+    }
+
+    // Index and value sorts, rejecting zero widths
+    fn sorts(address_width: u32, value_width: u32) -> MemoryResult<(Sort, Sort)> {
+        let (index, value) = (Sort::BitVec(address_width), Sort::BitVec(value_width));
+        index.check()?;
+        value.check()?;
+        Ok((index, value))
+    }
+
+    fn from_array(array: BaseMemorySymbolicArray, address_width: u32, value_width: u32) -> Self {
+        Self {
+            _inner: array,
+            address_width,
+            value_width,
+        }
+    }
+}
+
+impl ReadOnlyMem for BaseMemoryBitVecIndex {
+    type MemVal = Expr;
+
+    type Index = Expr;
+
+    fn read(&self, idx: Self::Index) -> MemoryResult<Option<Self::MemVal>> {
+        self._inner.read(idx)
+    }
+}
+
+impl WriteableMem for BaseMemoryBitVecIndex {
+    type MemVal = Expr;
+
+    type Index = Expr;
+
+    fn write(&self, idx: Self::Index, val: Self::MemVal) -> MemoryResult<Self> {
+        Ok(Self::from_array(
+            self._inner.write(idx, val)?,
+            self.address_width,
+            self.value_width,
+        ))
+    }
+}
+
+impl RWMem for BaseMemoryBitVecIndex {
+    // Address and value widths. Each memory gets a fresh name, so memories made this way never
+    // alias.
+    type InitArgs = (u32, u32);
+
+    fn init(args: Self::InitArgs) -> MemoryResult<Self> {
+        let (address_width, value_width) = args;
+        Self::fresh("memory", address_width, value_width)
+    }
+}
+
+impl SymbolicMem for BaseMemoryBitVecIndex {
+    fn terms(&self) -> Vec<Expr> {
+        self._inner.terms()
+    }
+}
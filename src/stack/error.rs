@@ -1,6 +1,6 @@
 use thiserror::{self, Error};
 
-#[derive(Debug, Error)]
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
 pub enum StackError {
     #[error("Invalid stack value {0}")]
     IncorrectVal(String),
@@ -8,4 +8,6 @@ pub enum StackError {
     EmptyStack,
     #[error("Stack overflow")]
     StackOverflow,
+    #[error("Recorded pop of {expected} but the stack holds {actual}")]
+    PopMismatch { expected: String, actual: String },
 }
@@ -1,4 +1,8 @@
 pub mod error;
+use std::fmt;
+use std::ops::Range;
+use std::rc::Rc;
+
 use error::StackError;
 pub type StackResult<T> = Result<T, StackError>;
 pub trait Stack: Sized {
@@ -6,13 +10,55 @@ pub trait Stack: Sized {
     fn push<V: Into<Self::StackVal>>(&self, v: V) -> StackResult<Self>;
     fn pop(&self) -> StackResult<Self>;
 
-    fn peek<V: From<Self::StackVal>>(&self, idx: usize) -> Option<V>;
+    // The value `idx` places below the top
+    fn peek<V: From<Self::StackVal>>(&self, idx: usize) -> StackResult<V>;
+
+    // The values the given number of places below the top, from the top down
+    fn peek_range<V: From<Self::StackVal>>(&self, range: Range<usize>) -> StackResult<Vec<V>>;
+
+    // Removes the top `n` values, or fails without popping any if there are fewer
+    fn pop_n(&self, n: usize) -> StackResult<Self>;
+
+    // Pushes a copy of the value `n` places below the top, so `dup(0)` duplicates the top
+    fn dup(&self, n: usize) -> StackResult<Self>;
+
+    // Exchanges the top with the value `n` places below it, so `swap(1)` swaps the top two
+    fn swap(&self, n: usize) -> StackResult<Self>;
+}
+
+// Equality as far as it can be decided without a solver
+pub trait ConcreteEq {
+    // `None` when the values may or may not be equal, e.g. for distinct symbolic terms
+    fn concrete_eq(&self, other: &Self) -> Option<bool>;
+}
+
+impl ConcreteEq for u64 {
+    fn concrete_eq(&self, other: &Self) -> Option<bool> {
+        Some(self == other)
+    }
 }
 
 #[derive(Clone)]
 pub enum StackOpRecord<T> {
     Pop(T),
     Push(T),
+    // See `Stack::dup` and `Stack::swap`
+    Dup(usize),
+    Swap(usize),
+    // The popped values from the top down
+    PopN(Vec<T>),
+}
+
+impl<T> StackOpRecord<T> {
+    fn map<U>(self, popped: impl Fn(T) -> U, pushed: impl Fn(T) -> U) -> StackOpRecord<U> {
+        match self {
+            StackOpRecord::Pop(v) => StackOpRecord::Pop(popped(v)),
+            StackOpRecord::Push(v) => StackOpRecord::Push(pushed(v)),
+            StackOpRecord::Dup(n) => StackOpRecord::Dup(n),
+            StackOpRecord::Swap(n) => StackOpRecord::Swap(n),
+            StackOpRecord::PopN(vs) => StackOpRecord::PopN(vs.into_iter().map(popped).collect()),
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -31,40 +77,166 @@ where
         Self {
             changed: changes
                 .into_iter()
-                .map(|v| match v {
-                    StackOpRecord::Pop(v) => StackOpRecord::Pop(v.into()),
-                    StackOpRecord::Push(v) => StackOpRecord::Push(v.into()),
-                })
+                .map(|record| record.map(V::into, V::into))
                 .collect::<Vec<_>>(),
         }
     }
 
+    // Rewrites every recorded value with `f`
+    pub fn map_values(self, f: impl Fn(S::StackVal) -> S::StackVal) -> Self {
+        Self {
+            changed: self
+                .changed
+                .into_iter()
+                .map(|record| record.map(&f, &f))
+                .collect(),
+        }
+    }
+
+    // Popped values are taken on trust, see `apply_checked`
     pub fn apply(self, stack: S) -> StackResult<S> {
+        self.changed.into_iter().try_fold(stack, Self::apply_op)
+    }
+
+    fn apply_op(stack: S, record: StackOpRecord<S::StackVal>) -> StackResult<S> {
+        match record {
+            StackOpRecord::Pop(_) => stack.pop(),
+            StackOpRecord::Push(v) => stack.push(v),
+            StackOpRecord::Dup(n) => stack.dup(n),
+            StackOpRecord::Swap(n) => stack.swap(n),
+            StackOpRecord::PopN(vs) => stack.pop_n(vs.len()),
+        }
+    }
+
+    // Undoes the record on the stack it was applied to: pushed values are popped and popped
+    // values pushed back, last change first
+    pub fn revert(self, stack: S) -> StackResult<S> {
+        self.changed
+            .into_iter()
+            .rev()
+            .try_fold(stack, |s, record| match record {
+                StackOpRecord::Pop(v) => s.push(v),
+                StackOpRecord::Push(_) | StackOpRecord::Dup(_) => s.pop(),
+                StackOpRecord::Swap(n) => s.swap(n),
+                StackOpRecord::PopN(vs) => vs.into_iter().rev().try_fold(s, |s, v| s.push(v)),
+            })
+    }
+
+    // Like `apply`, but a recorded pop that provably differs from the value on top of the
+    // stack fails the record with `StackError::PopMismatch`. Symbolic values that might be
+    // equal, such as `x + 1` and `1 + x`, count as a match.
+    pub fn apply_checked(self, stack: S) -> StackResult<S>
+    where
+        S::StackVal: ConcreteEq + fmt::Debug,
+    {
+        let check = |expected: &S::StackVal, actual: &S::StackVal| {
+            if expected.concrete_eq(actual) == Some(false) {
+                return Err(StackError::PopMismatch {
+                    expected: format!("{expected:?}"),
+                    actual: format!("{actual:?}"),
+                });
+            }
+            Ok(())
+        };
         self.changed
             .into_iter()
-            .fold(Ok(stack), |cur_stack: StackResult<S>, record| {
-                match cur_stack {
-                    Ok(s) => {
-                        match record {
-                            StackOpRecord::Pop(_v) => {
-                                // Assert that pop() == v?
-                                s.pop()
-                            }
-                            StackOpRecord::Push(v) => s.push(v),
-                        }
+            .try_fold(stack, |s, record| match record {
+                StackOpRecord::Pop(expected) => {
+                    check(&expected, &s.peek(0)?)?;
+                    s.pop()
+                }
+                StackOpRecord::PopN(expected) => {
+                    let actual = s.peek_range::<S::StackVal>(0..expected.len())?;
+                    for (expected, actual) in expected.iter().zip(&actual) {
+                        check(expected, actual)?;
                     }
-                    Err(e) => Err(e),
+                    s.pop_n(expected.len())
                 }
+                record => Self::apply_op(s, record),
             })
     }
 }
 
-#[derive(Clone, Debug)]
-pub struct BaseStack<T>(Vec<T>);
+// A stack of at most `max_depth` values, unbounded by default. Stacks are persistent: push,
+// pop and clone share the cells below the top, so each costs O(1) however deep the stack is.
+pub struct BaseStack<T> {
+    top: Option<Rc<Cell<T>>>,
+    len: usize,
+    max_depth: Option<usize>,
+}
+
+struct Cell<T> {
+    value: T,
+    below: Option<Rc<Cell<T>>>,
+}
 
 impl<T> BaseStack<T> {
     pub fn init() -> Self {
-        Self(vec![])
+        Self {
+            top: None,
+            len: 0,
+            max_depth: None,
+        }
+    }
+
+    // Pushing onto a full stack fails with `StackError::StackOverflow`
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = Some(max_depth);
+        self
+    }
+
+    pub fn max_depth(&self) -> Option<usize> {
+        self.max_depth
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // The values from the top down
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        std::iter::successors(self.top.as_deref(), |cell| cell.below.as_deref())
+            .map(|cell| &cell.value)
+    }
+}
+
+impl<T> Clone for BaseStack<T> {
+    fn clone(&self) -> Self {
+        Self {
+            top: self.top.clone(),
+            len: self.len,
+            max_depth: self.max_depth,
+        }
+    }
+}
+
+// Listed bottom to top, like the `Vec` this used to be
+impl<T: fmt::Debug> fmt::Debug for BaseStack<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut values = self.iter().collect::<Vec<_>>();
+        values.reverse();
+        f.debug_struct("BaseStack")
+            .field("values", &values)
+            .field("max_depth", &self.max_depth)
+            .finish()
+    }
+}
+
+// Frees the cells no other stack shares one at a time, rather than recursively, so that
+// dropping a deep stack cannot overflow the call stack
+impl<T> Drop for BaseStack<T> {
+    fn drop(&mut self) {
+        let mut next = self.top.take();
+        while let Some(cell) = next {
+            next = match Rc::try_unwrap(cell) {
+                Ok(mut cell) => cell.below.take(),
+                Err(_) => None,
+            };
+        }
     }
 }
 
@@ -77,23 +249,85 @@ where
     where
         V: Into<Self::StackVal>,
     {
-        let mut new_self = self.clone();
-        new_self.0.push(val.into());
-        Ok(new_self)
+        if self.max_depth.is_some_and(|max| self.len >= max) {
+            return Err(StackError::StackOverflow);
+        }
+        Ok(Self {
+            top: Some(Rc::new(Cell {
+                value: val.into(),
+                below: self.top.clone(),
+            })),
+            len: self.len + 1,
+            max_depth: self.max_depth,
+        })
     }
 
     fn pop(&self) -> StackResult<Self> {
-        let mut new_self = self.clone();
-        new_self.0.pop();
-        Ok(new_self)
+        let top = self.top.as_ref().ok_or(StackError::EmptyStack)?;
+        Ok(Self {
+            top: top.below.clone(),
+            len: self.len - 1,
+            max_depth: self.max_depth,
+        })
+    }
+
+    fn peek<V>(&self, idx: usize) -> StackResult<V>
+    where
+        V: From<Self::StackVal>,
+    {
+        self.iter()
+            .nth(idx)
+            .map(|val| val.clone().into())
+            .ok_or(StackError::EmptyStack)
     }
 
-    fn peek<V>(&self, idx: usize) -> Option<V>
+    fn peek_range<V>(&self, range: Range<usize>) -> StackResult<Vec<V>>
     where
         V: From<Self::StackVal>,
     {
-        let last_idx = self.0.len() - 1;
-        let get_idx = last_idx - idx;
-        self.0.get(get_idx).cloned().map(|val| val.into())
+        if range.end > self.len {
+            return Err(StackError::EmptyStack);
+        }
+        Ok(self
+            .iter()
+            .skip(range.start)
+            .take(range.len())
+            .map(|val| val.clone().into())
+            .collect())
+    }
+
+    // Shares everything below the popped values
+    fn pop_n(&self, n: usize) -> StackResult<Self> {
+        if n > self.len {
+            return Err(StackError::EmptyStack);
+        }
+        let mut top = self.top.as_ref();
+        for _ in 0..n {
+            top = top.and_then(|cell| cell.below.as_ref());
+        }
+        Ok(Self {
+            top: top.cloned(),
+            len: self.len - n,
+            max_depth: self.max_depth,
+        })
+    }
+
+    fn dup(&self, n: usize) -> StackResult<Self> {
+        self.push(self.peek::<T>(n)?)
+    }
+
+    // Rebuilds the top `n + 1` cells and shares the rest
+    fn swap(&self, n: usize) -> StackResult<Self> {
+        let mut values = self.peek_range::<T>(0..n + 1)?;
+        values.swap(0, n);
+        let below = self.pop_n(n + 1)?;
+        Ok(values.into_iter().rev().fold(below, |s, value| Self {
+            top: Some(Rc::new(Cell {
+                value,
+                below: s.top.clone(),
+            })),
+            len: s.len + 1,
+            max_depth: s.max_depth,
+        }))
     }
 }
@@ -1,12 +1,21 @@
-use z3::{
-    ast::{Ast, Int},
-    Context,
-};
+// Not every test uses every helper
+#![allow(dead_code)]
 
-pub fn z3_int<'a>(i: u64, ctxt: &'a Context) -> z3::ast::Int<'a> {
-    Int::from_u64(&ctxt, i)
+use symbolic_stack_machines::expr::{Expr, Sort};
+use symbolic_stack_machines::machine::solver::{IncrementalSolver, SatResult, SolverBackend};
+use z3::{Config, Context};
+
+pub fn int(i: u64) -> Expr {
+    Expr::int(i)
+}
+
+pub fn int_var(name: &str) -> Expr {
+    Expr::var(name, Sort::Int)
 }
 
-pub fn z3_int_var<'a>(i: &str, ctxt: &'a Context) -> z3::ast::Int<'a> {
-    Int::new_const(&ctxt, i)
+// Whether `claim` holds for every value of its variables
+pub fn valid(claim: &Expr) -> bool {
+    let ctx = Context::new(&Config::default());
+    let mut solver = IncrementalSolver::new(&ctx);
+    solver.check_path(&[claim.not()]) == Ok(SatResult::Unsat)
 }
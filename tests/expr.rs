@@ -0,0 +1,267 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+
+use symbolic_stack_machines::expr::error::ExprError;
+#[cfg(feature = "z3")]
+use symbolic_stack_machines::expr::lower::Lowering;
+use symbolic_stack_machines::expr::{smtlib, Expr, Op, Sort};
+#[cfg(feature = "z3")]
+use z3::ast::Ast;
+#[cfg(feature = "z3")]
+use z3::{Config, Context, SatResult, Solver};
+
+fn hash(expr: &Expr) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    expr.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[test]
+fn test_structural_equality() {
+    let x = Expr::var("x", Sort::Int);
+    let a = x.clone() + Expr::int(1);
+    let b = Expr::var("x", Sort::Int) + Expr::int(1);
+
+    assert_eq!(a, b);
+    assert_eq!(hash(&a), hash(&b));
+    assert_ne!(a, x.clone() + Expr::int(2));
+    // Same name, different sort
+    assert_ne!(x, Expr::var("x", Sort::BitVec(32)));
+}
+
+#[test]
+fn test_sort_checking() {
+    let x = Expr::var("x", Sort::Int);
+    let v = Expr::var("v", Sort::BitVec(8));
+
+    assert_eq!(x.lt(&Expr::int(3)).sort(), &Sort::Bool);
+    assert_eq!(v.concat(&v).sort(), &Sort::BitVec(16));
+    assert_eq!(v.extract(3, 0).sort(), &Sort::BitVec(4));
+    assert_eq!(
+        Expr::app(Op::Add, vec![x.clone(), v.clone()]),
+        Err(ExprError::IllSorted {
+            op: "Add".to_string(),
+            args: vec![Sort::Int, Sort::BitVec(8)],
+        })
+    );
+    assert_eq!(
+        Expr::app(Op::Extract(8, 0), vec![v]),
+        Err(ExprError::ExtractOutOfRange {
+            high: 8,
+            low: 0,
+            width: 8,
+        })
+    );
+    assert_eq!(
+        Expr::app(Op::IntToBv(0), vec![x]),
+        Err(ExprError::ZeroWidth)
+    );
+    assert_eq!(
+        Sort::array(Sort::Int, Sort::BitVec(0)).check(),
+        Err(ExprError::ZeroWidth)
+    );
+    assert!(std::panic::catch_unwind(|| Expr::bv(1, 0)).is_err());
+    assert!(std::panic::catch_unwind(|| Expr::var("w", Sort::BitVec(0))).is_err());
+}
+
+#[test]
+fn test_pretty_printing() {
+    let x = Expr::var("x", Sort::Int);
+    let mem = Expr::var("memory", Sort::array(Sort::Int, Sort::BitVec(8)));
+    let byte = mem.select(&x);
+
+    assert_eq!(
+        (x.clone() - Expr::int(-5)).ge(&Expr::int(0)).to_string(),
+        "(>= (- x (- 5)) 0)"
+    );
+    assert_eq!(
+        byte.zero_ext(4).bvand(&Expr::bv(0xabc, 12)).to_string(),
+        "(bvand ((_ zero_extend 4) (select memory x)) #xabc)"
+    );
+    assert_eq!(Expr::bv(5, 3).to_string(), "#b101");
+    assert_eq!(
+        Expr::const_array(Sort::Int, &Expr::int(0)).to_string(),
+        "((as const (Array Int Int)) 0)"
+    );
+    assert_eq!(Expr::var("a b", Sort::Bool).to_string(), "|a b|");
+
+    let script = smtlib::script(&[byte._eq(&Expr::bv(1, 8))]);
+    assert_eq!(
+        script,
+        "(declare-fun memory () (Array Int (_ BitVec 8)))\n\
+         (declare-fun x () Int)\n\
+         (assert (= (select memory x) #x01))\n\
+         (check-sat)\n"
+    );
+}
+
+#[test]
+fn test_substitution() {
+    let x = Expr::var("x", Sort::Int);
+    let y = Expr::var("y", Sort::Int);
+    let e = (x.clone() + y.clone()).lt(&x);
+
+    let substitutions = HashMap::from([(x.clone(), Expr::int(7))]);
+    let replaced = e.substitute(&substitutions);
+
+    assert_eq!(replaced, (Expr::int(7) + y.clone()).lt(&Expr::int(7)));
+    assert_eq!(
+        replaced.vars().into_iter().collect::<Vec<_>>(),
+        vec![("y".to_string(), Sort::Int)]
+    );
+    // Untouched expressions are returned as they are
+    assert_eq!(y.substitute(&substitutions), y);
+}
+
+#[test]
+#[cfg(feature = "z3")]
+fn test_lowering_to_z3() {
+    let mut cfg = Config::default();
+    cfg.set_model_generation(true);
+    let ctx = Context::new(&cfg);
+
+    let x = Expr::var("x", Sort::BitVec(256));
+    let max = Expr::bv_from_limbs(&[u64::MAX; 4], 256);
+    // x + 1 wraps around to 0 only for the maximum value
+    let wraps = (x.clone() + Expr::bv(1, 256))._eq(&Expr::bv(0, 256));
+    let mem = Expr::var("memory", Sort::array(Sort::Int, Sort::Int));
+    let stored = mem
+        .store(&Expr::int(1), &Expr::int(-4))
+        .select(&Expr::int(1));
+
+    let mut lowering = Lowering::new(&ctx);
+    let solver = Solver::new(&ctx);
+    solver.assert(&lowering.lower(&wraps).as_bool().unwrap());
+    solver.assert(&lowering.lower(&stored.lt(&Expr::int(0))).as_bool().unwrap());
+    assert_eq!(solver.check(), SatResult::Sat);
+
+    let model = solver.get_model().unwrap();
+    let value = model.eval(&x.to_z3_bv(&ctx), true).unwrap();
+    assert_eq!(value, max.to_z3_bv(&ctx).simplify());
+
+    solver.assert(&x.to_z3_bv(&ctx).bvult(&max.to_z3_bv(&ctx)));
+    assert_eq!(solver.check(), SatResult::Unsat);
+}
+
+#[test]
+fn test_simplify() {
+    let x = Expr::var("x", Sort::Int);
+    let v = Expr::var("v", Sort::BitVec(8));
+    let p = Expr::var("p", Sort::Bool);
+
+    // Constant folding
+    assert_eq!(
+        (Expr::int(2) * Expr::int(3) - Expr::int(-1)).simplify(),
+        Expr::int(7)
+    );
+    assert_eq!(Expr::int(-7).rem(&Expr::int(3)).simplify(), Expr::int(2));
+    assert_eq!(
+        (Expr::bv(0xff, 8) + Expr::bv(2, 8)).simplify(),
+        Expr::bv(1, 8)
+    );
+    assert_eq!(
+        Expr::bv(0x80, 8).slt(&Expr::bv(0, 8)).simplify(),
+        Expr::bool(true)
+    );
+    assert_eq!(
+        Expr::bv(0x80, 8).sign_ext(8).simplify(),
+        Expr::bv(0xff80, 16)
+    );
+    assert_eq!(
+        Expr::bv(0xab, 8)
+            .concat(&Expr::bv(0xcd, 8))
+            .extract(11, 4)
+            .simplify(),
+        Expr::bv(0xbc, 8)
+    );
+    // -7 and 2 as bytes
+    let (a, b) = (Expr::bv(0xf9, 8), Expr::bv(2, 8));
+    assert_eq!(a.sdiv(&b).simplify(), Expr::bv(0xfd, 8));
+    assert_eq!(a.srem(&b).simplify(), Expr::bv(0xff, 8));
+    assert_eq!(a.smod(&b).simplify(), Expr::bv(1, 8));
+    assert_eq!(a.sdiv(&Expr::bv(0, 8)).simplify(), Expr::bv(1, 8));
+    assert_eq!(a.smod(&Expr::bv(0, 8)).simplify(), a);
+    // Bitvectors wider than a machine word fold too
+    let max = Expr::bv_from_limbs(&[u64::MAX; 4], 256);
+    assert_eq!(
+        (max.clone() + Expr::bv(1, 256)).simplify(),
+        Expr::bv(0, 256)
+    );
+    assert_eq!((max.clone() * max.clone()).simplify(), Expr::bv(1, 256));
+    assert_eq!(
+        max.div(&Expr::bv(1 << 63, 256)).simplify(),
+        Expr::bv_from_limbs(&[u64::MAX, u64::MAX, u64::MAX, 1], 256)
+    );
+    assert_eq!(
+        max.shl(&Expr::bv(192, 256))
+            .lshr(&Expr::bv(64, 256))
+            .simplify(),
+        Expr::bv_from_limbs(&[0, 0, u64::MAX, 0], 256)
+    );
+    assert_eq!(
+        Expr::bv(0x80, 8)
+            .sign_ext(248)
+            .ashr(&Expr::bv(300, 256))
+            .simplify(),
+        max
+    );
+    assert_eq!(
+        Expr::int(-1).int_to_bv(128).simplify(),
+        Expr::bv_from_limbs(&[u64::MAX; 2], 128)
+    );
+    assert_eq!(
+        Expr::bv(1, 64)
+            .concat(&Expr::bv(2, 64))
+            .bv_to_int()
+            .simplify(),
+        Expr::int((1 << 64) | 2_i128)
+    );
+    // Division by zero is left to the solver on Int
+    let by_zero = Expr::int(1).div(&Expr::int(0));
+    assert_eq!(by_zero.simplify(), by_zero);
+
+    // An ISZERO on a concrete value no longer needs a solver to branch on
+    let is_zero = Expr::int(5)
+        ._eq(&Expr::int(0))
+        .ite(&Expr::int(1), &Expr::int(0));
+    assert_eq!(is_zero._eq(&Expr::int(0)).simplify(), Expr::bool(true));
+
+    // Identities with symbolic operands
+    assert_eq!((x.clone() + Expr::int(0)).simplify(), x);
+    assert_eq!((Expr::int(1) * x.clone()).simplify(), x);
+    assert_eq!((v.clone() * Expr::bv(0, 8)).simplify(), Expr::bv(0, 8));
+    assert_eq!((x.clone() - x.clone()).simplify(), Expr::int(0));
+    assert_eq!(Expr::bool(true).and(&p).simplify(), p);
+    assert_eq!(p.or(&Expr::bool(true)).simplify(), Expr::bool(true));
+    assert_eq!(p.not().not().simplify(), p);
+    assert_eq!(p.ite(&x, &x).simplify(), x);
+    assert_eq!(x._eq(&x).simplify(), Expr::bool(true));
+    // Nothing to do
+    let lt = x.lt(&Expr::int(3));
+    assert_eq!(lt.simplify(), lt);
+
+    // Reads through stores and constant arrays
+    let mem = Expr::var("memory", Sort::array(Sort::Int, Sort::Int));
+    let stored = mem
+        .store(&Expr::int(1), &x)
+        .store(&Expr::int(2), &Expr::int(9));
+    assert_eq!(stored.select(&Expr::int(1)).simplify(), x);
+    assert_eq!(stored.select(&Expr::int(2)).simplify(), Expr::int(9));
+    assert_eq!(
+        stored.select(&Expr::int(3)).simplify(),
+        mem.select(&Expr::int(3))
+    );
+    assert_eq!(stored.select(&x).simplify(), stored.select(&x));
+    let zeros = Expr::const_array(Sort::Int, &Expr::int(0)).store(&x, &Expr::int(4));
+    assert_eq!(
+        zeros.select(&Expr::int(5)).simplify(),
+        zeros.select(&Expr::int(5))
+    );
+    assert_eq!(
+        Expr::const_array(Sort::Int, &Expr::int(0))
+            .select(&x)
+            .simplify(),
+        Expr::int(0)
+    );
+}
@@ -0,0 +1,395 @@
+use symbolic_stack_machines::expr::error::ExprError;
+use symbolic_stack_machines::expr::{smtlib, Expr, Sort};
+use symbolic_stack_machines::instructions::error::InstructionError;
+use symbolic_stack_machines::instructions::*;
+use symbolic_stack_machines::machine::error::MachineError;
+use symbolic_stack_machines::machine::limits::TerminationReason;
+use symbolic_stack_machines::machine::BaseMachine;
+use symbolic_stack_machines::memory::bytes::{ByteMemory, Endian};
+use symbolic_stack_machines::memory::error::MemoryError;
+use symbolic_stack_machines::memory::memory_models::{
+    BaseMemoryBitVecIndex, BaseMemoryConcreteIndex, BaseMemorySymbolicArray, BaseMemorySymbolicUF,
+};
+use symbolic_stack_machines::memory::{
+    MemOpRecord, MemRecord, RWMem, ReadOnlyMem, SymbolicMem, WriteableMem,
+};
+use symbolic_stack_machines::stack::{BaseStack, Stack, StackOpRecord, StackRecord};
+
+use z3::{Config, Context};
+mod common;
+
+use common::{int, int_var, valid};
+
+// Memory instructions taking the offset from the top of the stack
+#[derive(Clone, Debug)]
+enum Instruction {
+    Push(Expr),
+    MStore,
+    MLoad,
+}
+
+impl VMInstruction for Instruction {
+    type ValStack = BaseStack<Expr>;
+
+    type Mem = BaseMemoryConcreteIndex<Expr>;
+
+    fn exec(
+        &self,
+        stack: &Self::ValStack,
+        memory: &Self::Mem,
+    ) -> InstructionResult<ExecRecord<Self::ValStack, Self::Mem>> {
+        let mut change_log = ExecRecord {
+            stack_diff: None,
+            mem_diff: None,
+            successors: vec![],
+            halt: false,
+        };
+        let offset = || stack.peek::<Expr>(0).unwrap();
+        // The stack holds Int terms but the memory is indexed by usize, so the instruction converts
+        let concrete = |v: &Expr| {
+            v.as_u64().map(|v| v as usize).ok_or_else(|| {
+                InstructionError::InstructionExecutionFailure(format!("symbolic address {v}"))
+            })
+        };
+        match self {
+            Instruction::Push(v) => {
+                change_log.stack_diff = Some(StackRecord {
+                    changed: vec![StackOpRecord::Push(v.clone())],
+                });
+            }
+            Instruction::MStore => {
+                let val = stack.peek::<Expr>(1).unwrap();
+                // Out of range offsets are left for the memory to reject
+                let prev = memory
+                    .read(concrete(&offset())?)
+                    .unwrap_or(None)
+                    .unwrap_or_else(|| val.clone());
+                change_log.stack_diff = Some(StackRecord {
+                    changed: vec![
+                        StackOpRecord::Pop(offset()),
+                        StackOpRecord::Pop(val.clone()),
+                    ],
+                });
+                change_log.mem_diff = Some(MemRecord {
+                    diff: vec![MemOpRecord::Write((concrete(&offset())?, prev, val))],
+                });
+            }
+            Instruction::MLoad => {
+                let val = memory.read(concrete(&offset())?).unwrap().unwrap();
+                change_log.stack_diff = Some(StackRecord {
+                    changed: vec![StackOpRecord::Pop(offset()), StackOpRecord::Push(val)],
+                });
+            }
+        }
+        Ok(change_log)
+    }
+}
+
+#[test]
+fn test_concrete_index_memory() {
+    let mem = BaseMemoryConcreteIndex::<Expr>::init((4, int(0))).unwrap();
+
+    assert_eq!(mem.size(), 4);
+    // Uninitialised cells hold the default
+    assert_eq!(mem.read(3).unwrap(), Some(int(0)));
+
+    let x = int_var("x");
+    let written = mem.write(2, x.clone()).unwrap();
+    assert_eq!(written.read(2).unwrap(), Some(x.clone()));
+    // Writes produce a new memory
+    assert_eq!(mem.read(2).unwrap(), Some(int(0)));
+
+    assert_eq!(written.read(4), Err(MemoryError::InvalidIndex(4)));
+    assert_eq!(
+        written.write(7, x.clone()).err(),
+        Some(MemoryError::InvalidIndex(7))
+    );
+
+    // Memories spanning several pages copy only what a write touches
+    let big = BaseMemoryConcreteIndex::<Expr>::init((1000, int(0))).unwrap();
+    let a = big.write(999, int(1)).unwrap();
+    let b = a.write(0, int(2)).unwrap();
+    assert_eq!(b.size(), 1000);
+    assert_eq!(b.read(999).unwrap(), Some(int(1)));
+    assert_eq!(b.read(0).unwrap(), Some(int(2)));
+    assert_eq!(a.read(0).unwrap(), Some(int(0)));
+    assert_eq!(big.read(999).unwrap(), Some(int(0)));
+    assert_eq!(b.cells().count(), 1000);
+
+    // Only symbolic cells are terms, and unwritten ones count once through the default
+    assert!(b.terms().is_empty());
+    let y = int_var("y");
+    let c = b.write(500, y.clone()).unwrap();
+    assert_eq!(c.terms(), vec![y.clone()]);
+    let symbolic = BaseMemoryConcreteIndex::<Expr>::init((1000, x.clone())).unwrap();
+    assert_eq!(symbolic.terms(), vec![x.clone()]);
+    assert_eq!(symbolic.write(999, y.clone()).unwrap().terms(), vec![x, y]);
+}
+
+#[test]
+fn test_machine_with_concrete_memory() {
+    let ctx = Context::new(&Config::default());
+    let x = int_var("x");
+
+    let machine = BaseMachine::new(BaseStack::init(), (4, int(0)))
+        .unwrap()
+        .with_context(&ctx);
+    let pgm = vec![
+        Instruction::Push(x.clone()),
+        Instruction::Push(int(1)),
+        Instruction::MStore,
+        Instruction::Push(int(1)),
+        Instruction::MLoad,
+    ];
+    let report = machine.run_sym(&pgm).unwrap();
+    assert_eq!(report.reachable.len(), 1);
+    let leaf = &report.reachable[0];
+    assert_eq!(leaf.stack.peek::<Expr>(0), Ok(x.clone()));
+    assert_eq!(leaf.mem.read(0).unwrap(), Some(int(0)));
+    // The stored symbolic value is declared when exporting the path
+    assert!(leaf.to_smtlib().contains("(declare-fun x () Int)"));
+
+    let machine = BaseMachine::new(BaseStack::init(), (4, int(0)))
+        .unwrap()
+        .with_context(&ctx);
+    let pgm = vec![
+        Instruction::Push(x),
+        Instruction::Push(int(4)),
+        Instruction::MStore,
+    ];
+    let report = machine.run_sym(&pgm).unwrap();
+    // The path is feasible; it ends with the memory error
+    let leaf = &report.reachable[0];
+    assert!(matches!(
+        leaf.reason,
+        TerminationReason::Error(MachineError::Memory {
+            pc: 2,
+            source: MemoryError::InvalidIndex(4),
+            ..
+        })
+    ));
+}
+
+#[test]
+fn test_uninterpreted_function_memory() {
+    let calldata =
+        BaseMemorySymbolicUF::init(("calldata".to_string(), Sort::Int, Sort::Int)).unwrap();
+    let x = int_var("x");
+    let y = int_var("y");
+
+    // Reads are function applications: equal indices read equal values, others are unrelated
+    let at_x = calldata.read(x.clone()).unwrap().unwrap();
+    let at_y = calldata.read(y.clone()).unwrap().unwrap();
+    assert_eq!(at_x.to_string(), "(calldata x)");
+    assert!(valid(&x._eq(&y).implies(&at_x._eq(&at_y))));
+    assert!(!valid(&at_x._eq(&at_y)));
+
+    // Writes are seen through the log; concrete indices are resolved without a solver
+    let written = calldata
+        .write(int(1), int(7))
+        .unwrap()
+        .write(x.clone(), int(9))
+        .unwrap();
+    assert_eq!(written.writes().len(), 2);
+    assert!(calldata.writes().is_empty());
+    assert_eq!(written.read(x.clone()).unwrap(), Some(int(9)));
+    let at_one = written.read(int(1)).unwrap().unwrap();
+    assert!(valid(&x._eq(&int(1)).implies(&at_one._eq(&int(9)))));
+    assert!(valid(&x._eq(&int(1)).not().implies(&at_one._eq(&int(7)))));
+    let at_two = written.read(int(2)).unwrap().unwrap();
+    assert!(valid(
+        &x._eq(&int(2))
+            .not()
+            .implies(&at_two._eq(&calldata.read(int(2)).unwrap().unwrap()))
+    ));
+
+    // Exported scripts declare the function
+    let mut terms = vec![at_x._eq(&int(3))];
+    terms.extend(written.terms());
+    let script = smtlib::script(&terms);
+    assert!(script.contains("(declare-fun calldata (Int) Int)"));
+    assert!(script.contains("(declare-fun x () Int)"));
+}
+
+#[test]
+fn test_byte_addressed_loads_and_stores() {
+    let addr = |a| Expr::bv(a, 64);
+    let mem = BaseMemorySymbolicArray::init((Sort::BitVec(64), Sort::BitVec(8))).unwrap();
+
+    // Unaligned 4 byte store read back in both byte orders
+    let val = Expr::bv(0x1122_3344, 32);
+    let big = mem.store(&addr(3), &val, Endian::Big).unwrap();
+    assert_eq!(
+        big.read(addr(3)).unwrap().unwrap().simplify(),
+        Expr::bv(0x11, 8)
+    );
+    assert_eq!(big.load(&addr(3), 4, Endian::Big).unwrap().simplify(), val);
+    assert_eq!(
+        big.load(&addr(3), 4, Endian::Little).unwrap().simplify(),
+        Expr::bv(0x4433_2211, 32)
+    );
+    // Overlapping a stored word with a wider load
+    assert_eq!(
+        big.load(&addr(5), 2, Endian::Big).unwrap().simplify(),
+        Expr::bv(0x3344, 16)
+    );
+
+    let little = mem
+        .store_u64(&addr(0), 0x0102_0304_0506_0708, Endian::Little)
+        .unwrap();
+    assert_eq!(
+        little.read(addr(0)).unwrap().unwrap().simplify(),
+        Expr::bv(0x08, 8)
+    );
+    assert_eq!(
+        little.load(&addr(0), 8, Endian::Little).unwrap().simplify(),
+        Expr::bv(0x0102_0304_0506_0708, 64)
+    );
+
+    // Symbolic values and offsets round trip
+    let x = Expr::var("x", Sort::BitVec(64));
+    let offset = Expr::var("offset", Sort::BitVec(64));
+    let stored = mem.store(&offset, &x, Endian::Little).unwrap();
+    let loaded = stored.load(&offset, 8, Endian::Little).unwrap();
+    assert!(valid(&loaded._eq(&x)));
+
+    assert_eq!(
+        mem.store(&addr(0), &Expr::bv(1, 12), Endian::Big).err(),
+        Some(MemoryError::ValueNotSupported(
+            "store of 12 bits, which is not a whole number of bytes".to_string()
+        ))
+    );
+
+    // Byte access to wider cells fails the same way for loads and stores
+    let words = BaseMemorySymbolicArray::init((Sort::BitVec(64), Sort::BitVec(32))).unwrap();
+    let wide = Some(MemoryError::ValueNotSupported(
+        "byte access to a memory of 32 bit cells".to_string(),
+    ));
+    assert_eq!(words.load(&addr(0), 4, Endian::Big).err(), wide);
+    assert_eq!(words.store(&addr(0), &val, Endian::Big).err(), wide);
+    assert_eq!(words.store_bytes(&addr(0), &[1, 2]).err(), wide);
+}
+
+#[test]
+fn test_bitvector_indexed_memory() {
+    let addr = |a| Expr::bv(a, 16);
+    let mem = BaseMemoryBitVecIndex::init((16, 8)).unwrap();
+    assert_eq!((mem.address_width(), mem.value_width()), (16, 8));
+
+    // A word stored at the last address wraps around to the first ones
+    let stored = mem
+        .store(&addr(0xffff), &Expr::bv(0xaabb, 16), Endian::Big)
+        .unwrap();
+    assert_eq!(
+        stored.read(addr(0)).unwrap().unwrap().simplify(),
+        Expr::bv(0xbb, 8)
+    );
+    assert_eq!(
+        stored
+            .load(&addr(0xffff), 2, Endian::Big)
+            .unwrap()
+            .simplify(),
+        Expr::bv(0xaabb, 16)
+    );
+
+    // Memories of different machines are unrelated unknowns unless they share a name
+    let first = BaseMemoryBitVecIndex::fresh("memory", 16, 8).unwrap();
+    let second = BaseMemoryBitVecIndex::fresh("memory", 16, 8).unwrap();
+    assert!(!valid(
+        &first
+            .read(addr(7))
+            .unwrap()
+            .unwrap()
+            ._eq(&second.read(addr(7)).unwrap().unwrap())
+    ));
+    let named = BaseMemoryBitVecIndex::named("shared", 16, 8).unwrap();
+    let same = BaseMemoryBitVecIndex::named("shared", 16, 8).unwrap();
+    assert!(valid(
+        &named
+            .read(addr(7))
+            .unwrap()
+            .unwrap()
+            ._eq(&same.read(addr(7)).unwrap().unwrap())
+    ));
+    // Memories made by `init` get fresh names
+    let other = BaseMemoryBitVecIndex::init((16, 8)).unwrap();
+    assert!(!valid(
+        &other
+            .read(addr(7))
+            .unwrap()
+            .unwrap()
+            ._eq(&mem.read(addr(7)).unwrap().unwrap())
+    ));
+
+    let zeroed = BaseMemoryBitVecIndex::filled(16, &Expr::bv(0, 8)).unwrap();
+    assert_eq!(zeroed.value_width(), 8);
+    assert_eq!(
+        zeroed.read(addr(7)).unwrap().unwrap().simplify(),
+        Expr::bv(0, 8)
+    );
+
+    // Zero-width addresses or cells are errors rather than panics
+    assert_eq!(
+        BaseMemoryBitVecIndex::init((0, 8)).unwrap_err(),
+        MemoryError::Expr(ExprError::ZeroWidth)
+    );
+    assert!(BaseMemoryBitVecIndex::init((16, 0)).is_err());
+    assert!(BaseMemoryBitVecIndex::filled(16, &int(0)).is_err());
+}
+
+#[test]
+fn test_reverting_records() {
+    let x = int_var("x");
+    let stack = BaseStack::<Expr>::init()
+        .push(x.clone())
+        .and_then(|s| s.push(int(1)))
+        .unwrap();
+    let mem = BaseMemoryConcreteIndex::<Expr>::init((4, int(0))).unwrap();
+
+    // Step forwards, keeping each instruction's records
+    let pgm = [
+        Instruction::MStore,
+        Instruction::Push(int(1)),
+        Instruction::MLoad,
+    ];
+    let mut history = vec![];
+    let (mut s, mut m) = (stack.clone(), mem.clone());
+    for inst in &pgm {
+        let rec = inst.exec(&s, &m).unwrap();
+        if let Some(diff) = &rec.stack_diff {
+            s = diff.clone().apply(s).unwrap();
+        }
+        if let Some(diff) = &rec.mem_diff {
+            m = diff.clone().apply(m).unwrap();
+        }
+        history.push(rec);
+    }
+    assert_eq!(s.peek::<Expr>(0), Ok(x.clone()));
+    assert_eq!(m.read(1).unwrap(), Some(x.clone()));
+
+    // And back again
+    for rec in history.into_iter().rev() {
+        if let Some(diff) = rec.mem_diff {
+            m = diff.revert(m).unwrap();
+        }
+        if let Some(diff) = rec.stack_diff {
+            s = diff.revert(s).unwrap();
+        }
+    }
+    assert_eq!(s.len(), 2);
+    assert_eq!(s.peek::<Expr>(0), Ok(int(1)));
+    assert_eq!(s.peek::<Expr>(1), Ok(x.clone()));
+    assert_eq!(m.read(1).unwrap(), Some(int(0)));
+
+    // Writes to the same cell are undone newest first
+    let twice = MemRecord::<BaseMemoryConcreteIndex<Expr>> {
+        diff: vec![
+            MemOpRecord::Write((0, int(0), int(5))),
+            MemOpRecord::Write((0, int(5), int(6))),
+        ],
+    };
+    let written = twice.clone().apply(mem).unwrap();
+    assert_eq!(written.read(0).unwrap(), Some(int(6)));
+    let reverted = twice.revert(written).unwrap();
+    assert_eq!(reverted.read(0).unwrap(), Some(int(0)));
+}
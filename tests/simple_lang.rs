@@ -1,12 +1,24 @@
+use symbolic_stack_machines::instructions::error::InstructionError;
+use symbolic_stack_machines::machine::debugger::{Breakpoint, Pause};
+use symbolic_stack_machines::machine::error::MachineError;
+use symbolic_stack_machines::machine::limits::{ExplorationLimits, TerminationReason};
+use symbolic_stack_machines::machine::observer::MachineObserver;
+use symbolic_stack_machines::machine::report::{ExplorationReport, PathOutcome};
+use symbolic_stack_machines::machine::smtlib::SmtLibSolver;
+use symbolic_stack_machines::machine::symbols::SymbolRegistry;
 use symbolic_stack_machines::memory::{MemOpRecord, MemRecord, ReadOnlyMem};
+use symbolic_stack_machines::stack::error::StackError;
 use symbolic_stack_machines::{instructions::*, machine::*, memory::memory_models::*, stack::*};
 
+use symbolic_stack_machines::expr::model::Model;
+use symbolic_stack_machines::expr::{Expr, Sort};
+use symbolic_stack_machines::machine::solver::SatResult;
+
 use std::rc::Rc;
-use z3::ast::{Ast, Bool, Int};
 use z3::{Config, Context};
 mod common;
 
-use common::{z3_int, z3_int_var};
+use common::{int, int_var};
 
 #[derive(Clone, PartialEq, Eq, Debug)]
 pub enum Instruction<T> {
@@ -18,31 +30,32 @@ pub enum Instruction<T> {
     MSTORE,
     ISZERO,
     JUMPI,
+    JUMP,
     STOP,
+    INVALID,
 }
 
-impl<'a> VMInstruction<'a> for Instruction<Int<'a>> {
-    type ValStack = BaseStack<Int<'a>>;
+impl VMInstruction for Instruction<Expr> {
+    type ValStack = BaseStack<Expr>;
 
-    type Mem = MemIntToInt<'a>;
+    type Mem = BaseMemorySymbolicArray;
 
     fn exec(
         &self,
         stack: &Self::ValStack,
         memory: &Self::Mem,
-    ) -> InstructionResult<ExecRecord<'a, Self::ValStack, Self::Mem>> {
-        let mut change_log: ExecRecord<'a, Self::ValStack, Self::Mem> = ExecRecord {
+    ) -> InstructionResult<ExecRecord<Self::ValStack, Self::Mem>> {
+        let mut change_log: ExecRecord<Self::ValStack, Self::Mem> = ExecRecord {
             stack_diff: None,
             mem_diff: None,
-            path_constraints: vec![],
-            pc_change: None,
+            successors: vec![],
             halt: false,
         };
         match self {
             Instruction::Add => {
-                let op_1 = stack.peek(0).unwrap();
-                let op_2 = stack.peek(1).unwrap();
-                let res = &op_1 + &op_2;
+                let op_1 = stack.peek(0)?;
+                let op_2 = stack.peek(1)?;
+                let res = Expr::add(&op_1, &op_2);
                 change_log.stack_diff = Some(StackRecord {
                     changed: vec![
                         StackOpRecord::Pop(op_1),
@@ -52,9 +65,9 @@ impl<'a> VMInstruction<'a> for Instruction<Int<'a>> {
                 });
             }
             Instruction::Sub => {
-                let op_1 = stack.peek(0).unwrap();
-                let op_2 = stack.peek(1).unwrap();
-                let res = &op_1 - &op_2;
+                let op_1 = stack.peek(0)?;
+                let op_2 = stack.peek(1)?;
+                let res = Expr::sub(&op_1, &op_2);
                 change_log.stack_diff = Some(StackRecord {
                     changed: vec![
                         StackOpRecord::Pop(op_1),
@@ -69,12 +82,14 @@ impl<'a> VMInstruction<'a> for Instruction<Int<'a>> {
                 });
             }
             Instruction::Assert(v) => {
-                let stack_top = stack.peek::<Int<'a>>(0).unwrap();
+                let stack_top = stack.peek::<Expr>(0)?;
                 let constraint = stack_top._eq(v);
-                change_log.path_constraints.push(vec![constraint]);
+                change_log
+                    .successors
+                    .push(Successor::next(vec![constraint]));
             }
             Instruction::MLOAD => {
-                let mem_offset = stack.peek::<Int<'a>>(0).unwrap();
+                let mem_offset = stack.peek::<Expr>(0)?;
                 let val = {
                     match memory.read(mem_offset.clone()) {
                         Ok(val) => val.unwrap(),
@@ -88,12 +103,12 @@ impl<'a> VMInstruction<'a> for Instruction<Int<'a>> {
                 });
             }
             Instruction::MSTORE => {
-                let mem_offset = stack.peek::<Int<'a>>(0).unwrap();
-                let val = stack.peek::<Int<'a>>(1).unwrap();
+                let mem_offset = stack.peek::<Expr>(0)?;
+                let val = stack.peek::<Expr>(1)?;
                 let prev_val = {
                     match memory.read(mem_offset.clone()) {
                         Ok(val) => val.unwrap(),
-                        Err(e) => Int::from_u64(val.get_ctx(), 0),
+                        Err(_) => Expr::int(0),
                     }
                 };
                 change_log.stack_diff = Some(StackRecord {
@@ -107,10 +122,9 @@ impl<'a> VMInstruction<'a> for Instruction<Int<'a>> {
                 });
             }
             Instruction::ISZERO => {
-                let top = stack.peek::<Int<'a>>(0).unwrap();
-                let zero = Int::from_u64(top.get_ctx(), 0);
-                let one = Int::from_u64(top.get_ctx(), 1);
-                let is_zero = Bool::ite(&top._eq(&zero), &one, &zero);
+                let top = stack.peek::<Expr>(0)?;
+                let zero = Expr::int(0);
+                let is_zero = top._eq(&zero).ite(&Expr::int(1), &zero);
                 change_log.stack_diff = Some(StackRecord {
                     changed: vec![
                         StackOpRecord::Pop(top.clone()),
@@ -119,21 +133,31 @@ impl<'a> VMInstruction<'a> for Instruction<Int<'a>> {
                 });
             }
             Instruction::JUMPI => {
-                let dest = stack.peek::<Int<'a>>(0).unwrap();
-                let ctx = dest.ctx;
-                let cond = stack.peek::<Int<'a>>(1).unwrap();
-                if let Some(dest) = dest.as_u64() {
-                    let zero = Int::from_u64(ctx, 0);
-                    change_log.path_constraints.push(vec![cond._eq(&zero)]);
-                    change_log
-                        .path_constraints
-                        .push(vec![Bool::not(&cond._eq(&zero))]);
-                    change_log.pc_change = Some(dest as usize);
-                }
+                let dest = stack.peek::<Expr>(0)?;
+                let cond = stack.peek::<Expr>(1)?;
+                let zero = Expr::int(0);
+                let taken = vec![cond._eq(&zero).not()];
+                change_log.successors = vec![
+                    Successor::next(vec![cond._eq(&zero)]),
+                    match dest.as_u64() {
+                        Some(dest) => Successor::jump(dest as usize, taken),
+                        None => Successor::symbolic_jump(dest, taken)?,
+                    },
+                ];
+            }
+            Instruction::JUMP => {
+                let dest = stack.peek::<Expr>(0)?;
+                change_log.successors = vec![match dest.as_u64() {
+                    Some(dest) => Successor::jump(dest as usize, vec![]),
+                    None => Successor::symbolic_jump(dest, vec![])?,
+                }];
             }
             Instruction::STOP => {
                 change_log.halt = true;
             }
+            Instruction::INVALID => {
+                return Err(InstructionError::UnknownInstruction("INVALID".to_string()));
+            }
         };
         Ok(change_log)
     }
@@ -168,28 +192,36 @@ pub fn jumpi<T>() -> Instruction<T> {
     Instruction::JUMPI
 }
 
+pub fn jump<T>() -> Instruction<T> {
+    Instruction::JUMP
+}
+
 pub fn stop<T>() -> Instruction<T> {
     Instruction::STOP
 }
 
+pub fn invalid<T>() -> Instruction<T> {
+    Instruction::INVALID
+}
+
 #[test]
 fn test_basic_sym_mem() {
     let mut cfg = Config::default();
     cfg.set_model_generation(true);
     let ctx = Context::new(&cfg);
 
-    let stack: BaseStack<Int> = BaseStack::init();
-    let machine = BaseMachine::new_with_ctx(stack, Rc::new(&ctx));
+    let stack: BaseStack<Expr> = BaseStack::init();
+    let machine = BaseMachine::new_with_ctx(stack, &ctx);
     let pgm = vec![
-        push(z3_int_var("a", &ctx)),
-        push(z3_int(3, &ctx)),
-        push(z3_int_var("c", &ctx)),
+        push(int_var("a")),
+        push(int(3)),
+        push(int_var("c")),
         add(),
         sub(),
-        assert(z3_int(4, &ctx)),
+        assert(int(4)),
     ];
 
-    let _res = machine.run_sym(&pgm);
+    let _res = machine.run_sym(&pgm).unwrap();
 }
 
 #[test]
@@ -198,45 +230,62 @@ fn test_jumpi() {
     cfg.set_model_generation(true);
     let ctx = Context::new(&cfg);
 
-    let stack: BaseStack<Int> = BaseStack::init();
-    let machine = BaseMachine::new_with_ctx(stack, Rc::new(&ctx));
+    let stack: BaseStack<Expr> = BaseStack::init();
+    let machine = BaseMachine::new_with_ctx(stack, &ctx);
     let pgm = vec![
-        push(z3_int(1, &ctx)),
-        push(z3_int(2, &ctx)),
-        push(z3_int(3, &ctx)),
+        push(int(1)),
+        push(int(2)),
+        push(int(3)),
         add(),
         sub(),
-        push(z3_int(4, &ctx)),
+        push(int(4)),
         sub(),
         is_zero(),
-        push(z3_int(12, &ctx)),
+        push(int(12)),
         jumpi(),
-        push(z3_int(100, &ctx)),
+        push(int(100)),
         stop(),
-        push(z3_int(200, &ctx)),
+        push(int(200)),
     ];
 
-    let res = machine.run_sym(&pgm);
-    let (reachable, unreachable) = res;
-    let first_path_reachable_stack: &BaseStack<Int> = &reachable.first().unwrap().0 .1;
-    let first_path_unreachable_stack: &BaseStack<Int> = &unreachable.first().unwrap().0 .1;
+    let report = machine.run_sym(&pgm).unwrap();
 
+    // The jump condition is concrete, so the fall through branch is never forked
+    assert_eq!(report.reachable.len(), 1);
+    assert!(report.unreachable.is_empty());
+    let reachable = report.reachable.first().unwrap();
     assert_eq!(
-        first_path_reachable_stack
-            .peek::<Int>(0)
-            .unwrap()
-            .as_u64()
-            .unwrap(),
+        reachable.stack.peek::<Expr>(0).unwrap().as_u64().unwrap(),
         200
     );
-    assert_eq!(
-        first_path_unreachable_stack
-            .peek::<Int>(0)
-            .unwrap()
-            .as_u64()
-            .unwrap(),
-        100
-    );
+    assert_eq!(reachable.reason, TerminationReason::FellOffProgram);
+    assert_eq!(reachable.trace, vec![0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 12]);
+    assert!(reachable.constraints.is_empty());
+    // The path has no constraints, so any model will do and the solver is never asked
+    assert_eq!(reachable.model, Some(Model::default()));
+    assert_eq!(report.stats.forks, 0);
+    assert_eq!(report.stats.pruned, 0);
+    assert_eq!(report.stats.steps, 11);
+    assert_eq!(report.stats.solver_queries, 0);
+}
+
+#[test]
+fn test_concrete_assert_failure() {
+    let mut cfg = Config::default();
+    cfg.set_model_generation(true);
+    let ctx = Context::new(&cfg);
+
+    let machine = BaseMachine::new_with_ctx(BaseStack::init(), &ctx);
+    let pgm = vec![push(int(3)), assert(int(4)), stop()];
+
+    let report = machine.run_sym(&pgm).unwrap();
+
+    // The failed assertion ends the path without asking the solver
+    assert!(report.reachable.is_empty());
+    assert_eq!(report.unreachable.len(), 1);
+    assert_eq!(report.unreachable[0].reason, TerminationReason::Infeasible);
+    assert_eq!(report.unreachable[0].pc, 2);
+    assert_eq!(report.stats.solver_queries, 0);
 }
 
 #[test]
@@ -245,31 +294,809 @@ fn test_multi_jumpi() {
     cfg.set_model_generation(true);
     let ctx = Context::new(&cfg);
 
-    let stack: BaseStack<Int> = BaseStack::init();
-    let machine = BaseMachine::new_with_ctx(stack, Rc::new(&ctx));
+    let stack: BaseStack<Expr> = BaseStack::init();
+    let machine = BaseMachine::new_with_ctx(stack, &ctx);
     let pgm = vec![
-        push(z3_int(1, &ctx)),
-        push(z3_int(2, &ctx)),
-        push(z3_int(3, &ctx)),
+        push(int(1)),
+        push(int(2)),
+        push(int(3)),
         add(),
         sub(),
-        push(z3_int(3, &ctx)),
+        push(int(3)),
         sub(),
-        push(z3_int(13, &ctx)),
+        push(int(13)),
         jumpi(),
-        push(z3_int(100, &ctx)),
+        push(int(100)),
         stop(),
         stop(),
         stop(),
-        push(z3_int(200, &ctx)),
-        push(z3_int(201, &ctx)),
+        push(int(200)),
+        push(int(201)),
         sub(),
-        push(z3_int(19, &ctx)),
+        push(int(19)),
         jumpi(),
         stop(),
-        push(z3_int(300, &ctx)),
+        push(int(300)),
         stop(),
     ];
 
-    let _res = machine.run_sym(&pgm);
+    let _res = machine.run_sym(&pgm).unwrap();
+}
+
+#[test]
+fn test_symbolic_jump() {
+    let mut cfg = Config::default();
+    cfg.set_model_generation(true);
+    let ctx = Context::new(&cfg);
+
+    let machine = || BaseMachine::new_with_ctx(BaseStack::init(), &ctx);
+    // Jumps to 6 + iszero(x)
+    let pgm = vec![
+        push(int_var("x")),
+        is_zero(),
+        push(int(6)),
+        add(),
+        jump(),
+        invalid(),
+        stop(),
+        push(int(200)),
+    ];
+
+    let report = machine().run_sym(&pgm).unwrap();
+
+    // One successor per feasible destination
+    assert_eq!(report.reachable.len(), 2);
+    assert!(report.unreachable.is_empty());
+    assert_eq!(report.stats.forks, 1);
+    let x = int_var("x");
+    for leaf in &report.reachable {
+        let x = leaf.model.as_ref().unwrap().eval(&x);
+        match leaf.reason {
+            TerminationReason::Halted => {
+                assert_eq!(leaf.trace, vec![0, 1, 2, 3, 4, 6]);
+                assert_ne!(x.as_int().unwrap(), 0);
+            }
+            TerminationReason::FellOffProgram => {
+                assert_eq!(leaf.trace, vec![0, 1, 2, 3, 4, 7]);
+                assert_eq!(x.as_int().unwrap(), 0);
+            }
+            ref reason => panic!("unexpected leaf {reason:?}"),
+        }
+    }
+
+    // Without a solver the destination cannot be resolved
+    assert_eq!(
+        machine().run(&pgm).unwrap_err(),
+        MachineError::SymbolicBranch { pc: 4, path_id: 0 }
+    );
+
+    // A jump with more destinations than the limit ends its path instead of forking
+    let limited = |max_jump_targets| {
+        machine()
+            .with_limits(ExplorationLimits {
+                max_jump_targets: Some(max_jump_targets),
+                ..Default::default()
+            })
+            .run_sym(&pgm)
+            .unwrap()
+    };
+    assert_eq!(limited(2).reachable.len(), 2);
+    let report = limited(1);
+    assert_eq!(report.len(), 1);
+    assert_eq!(
+        report.reachable[0].reason,
+        TerminationReason::Error(MachineError::JumpTargets {
+            pc: 4,
+            path_id: 0,
+            limit: 1
+        })
+    );
+
+    // Only Int and bitvector destinations can be jumped to
+    assert_eq!(
+        Successor::symbolic_jump(Expr::bool(true), vec![]).unwrap_err(),
+        InstructionError::JumpDestination(Sort::Bool)
+    );
+
+    // Jumps to 5 + iszero(x), which is past the end of the program when x == 0
+    let pgm = vec![
+        push(int_var("x")),
+        is_zero(),
+        push(int(5)),
+        add(),
+        jump(),
+        stop(),
+    ];
+    let report = machine().run_sym(&pgm).unwrap();
+    assert_eq!(report.reachable.len(), 2);
+    let outside = report
+        .reachable
+        .iter()
+        .find(|leaf| leaf.reason == TerminationReason::FellOffProgram)
+        .unwrap();
+    assert_eq!(outside.pc, 6);
+    assert_eq!(outside.trace, vec![0, 1, 2, 3, 4]);
+    let x = outside.model.as_ref().unwrap().eval(&int_var("x"));
+    assert_eq!(x.as_int(), Some(0));
+}
+
+#[test]
+#[ignore = "needs a z3 binary"]
+fn test_smtlib_solver() {
+    let mut cfg = Config::default();
+    cfg.set_model_generation(true);
+    let ctx = Context::new(&cfg);
+
+    let solver = SmtLibSolver::new("z3", &["-in"]).expect("no z3 binary on the PATH");
+    let machine = BaseMachine::new_with_ctx(BaseStack::init(), &ctx).with_solver(solver);
+    // Jumps to 13 if x == 5, otherwise to 11 + iszero(x)
+    let pgm = vec![
+        push(int_var("x")),
+        push(int(5)),
+        sub(),
+        is_zero(),
+        push(int(13)),
+        jumpi(),
+        push(int_var("x")),
+        is_zero(),
+        push(int(11)),
+        add(),
+        jump(),
+        stop(),
+        push(int(200)),
+        stop(),
+    ];
+
+    let report = machine.run_sym(&pgm).unwrap();
+
+    assert_eq!(report.reachable.len(), 3);
+    assert!(report.unknown.is_empty());
+    let x = int_var("x");
+    for leaf in &report.reachable {
+        let x = leaf.model.as_ref().unwrap().eval(&x);
+        let x = x.as_int().unwrap();
+        match leaf.trace[..] {
+            [0, 1, 2, 3, 4, 5, 13] => assert_eq!(x, 5),
+            [.., 10, 11] => assert!(x != 0 && x != 5),
+            [.., 10, 12, 13] => assert_eq!(x, 0),
+            _ => panic!("unexpected trace {:?}", leaf.trace),
+        }
+    }
+}
+
+#[test]
+fn test_smtlib_export() {
+    let mut cfg = Config::default();
+    cfg.set_model_generation(true);
+    let ctx = Context::new(&cfg);
+
+    let machine = BaseMachine::new_with_ctx(BaseStack::init(), &ctx);
+    let pgm = vec![
+        push(int_var("a")),
+        push(int(5)),
+        sub(),
+        push(int(5)),
+        jumpi(),
+        stop(),
+    ];
+    let report = machine.run_sym(&pgm).unwrap();
+    assert_eq!(report.reachable.len(), 2);
+
+    let dir = std::env::temp_dir().join(format!("smtlib_export_{}", std::process::id()));
+    let files = report.write_smtlib(&dir).unwrap();
+    assert_eq!(files.len(), report.len());
+    for leaf in report.paths() {
+        let file = dir.join(format!("path_{}.smt2", leaf.path_id));
+        let script = std::fs::read_to_string(&file).unwrap();
+        assert!(script.contains("(set-info :status sat)"));
+        assert!(script.contains("(declare-fun a () Int)"));
+        // Declared under its fresh name even though no constraint reads memory
+        let memory = format!("(declare-fun {} () (Array Int Int))", leaf.mem._inner);
+        assert!(leaf.mem._inner.to_string().starts_with("memory!"));
+        assert!(script.contains(&memory));
+        assert_eq!(script.matches("(assert ").count(), leaf.constraints.len());
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+// Records each leaf as (forks, instructions executed) when it is reached
+struct LeafOrder(Rc<std::cell::RefCell<Vec<(usize, usize)>>>);
+
+impl<S, M> MachineObserver<S, M> for LeafOrder {
+    fn on_leaf(&mut self, outcome: &PathOutcome<S, M>) {
+        // Every fork in this program adds one path constraint
+        let forks = outcome.constraints.len();
+        self.0.borrow_mut().push((forks, outcome.trace.len()));
+    }
+}
+
+#[test]
+fn test_search_strategies() {
+    let mut cfg = Config::default();
+    cfg.set_model_generation(true);
+    let ctx = Context::new(&cfg);
+
+    let pgm = vec![
+        push(int_var("a")),
+        push(int(6)),
+        jumpi(),
+        push(int_var("b")),
+        push(int(10)),
+        jumpi(),
+        push(int(100)),
+        push(int_var("c")),
+        push(int(10)),
+        jumpi(),
+        push(int(200)),
+    ];
+
+    // Each leaf's number of forks and number of instructions, in the order leaves are reached
+    let leaves = Rc::new(std::cell::RefCell::new(vec![]));
+    let leaf_order = |report: ExplorationReport<BaseStack<Expr>, BaseMemorySymbolicArray>| {
+        assert_eq!(report.reachable.len() + report.unreachable.len(), 5);
+        leaves.take()
+    };
+
+    let machine = || {
+        BaseMachine::new_with_ctx(BaseStack::init(), &ctx).with_observer(LeafOrder(leaves.clone()))
+    };
+    let dfs = leaf_order(machine().run_sym(&pgm).unwrap());
+    let bfs = leaf_order(
+        machine()
+            .with_strategy(strategy::BreadthFirst::new())
+            .run_sym(&pgm)
+            .unwrap(),
+    );
+    let random = leaf_order(
+        machine()
+            .with_strategy(strategy::RandomSearch::new(42))
+            .run_sym(&pgm)
+            .unwrap(),
+    );
+    let coverage = leaf_order(
+        machine()
+            .with_strategy(strategy::CoverageGuided::new())
+            .run_sym(&pgm)
+            .unwrap(),
+    );
+    let shortest = leaf_order(
+        machine()
+            .with_strategy(strategy::ShortestPathFirst::new())
+            .run_sym(&pgm)
+            .unwrap(),
+    );
+    // Depth first finishes the deepest path before backtracking
+    assert_eq!(dfs, [(3, 11), (3, 11), (2, 7), (2, 8), (2, 8)]);
+    // Breadth first reaches leaves in order of depth
+    assert!(bfs.is_sorted_by_key(|(forks, _)| *forks));
+    // Shortest path first reaches leaves in order of trace length
+    assert!(shortest.is_sorted_by_key(|(_, steps)| *steps));
+    // A fixed seed always gives the same order
+    assert_eq!(random, [(2, 7), (3, 11), (2, 8), (3, 11), (2, 8)]);
+
+    // Every strategy reaches the same leaves
+    let sorted = |mut order: Vec<(usize, usize)>| {
+        order.sort_unstable();
+        order
+    };
+    for order in [&bfs, &random, &coverage, &shortest] {
+        assert_eq!(sorted(order.clone()), sorted(dfs.clone()));
+    }
+}
+
+#[test]
+fn test_prune_infeasible_fork() {
+    let mut cfg = Config::default();
+    cfg.set_model_generation(true);
+    let ctx = Context::new(&cfg);
+
+    let stack: BaseStack<Expr> = BaseStack::init();
+    let machine = BaseMachine::new_with_ctx(stack, &ctx);
+    let pgm = vec![
+        push(int_var("a")),
+        push(int(6)),
+        jumpi(),
+        push(int_var("a")),
+        push(int(9)),
+        // Jumping here requires a == 0 and a != 0
+        jumpi(),
+        push(int(300)),
+        stop(),
+        stop(),
+        push(int(400)),
+    ];
+
+    let report = machine.run_sym(&pgm).unwrap();
+
+    assert_eq!(report.reachable.len(), 2);
+    for leaf in &report.reachable {
+        assert_eq!(leaf.stack.peek::<Expr>(0).unwrap().as_u64().unwrap(), 300);
+    }
+
+    // The infeasible jump is cut before `push(400)` executes
+    assert_eq!(report.unreachable.len(), 1);
+    assert_eq!(report.unreachable[0].pc, 9);
+    assert_eq!(report.stats.pruned, 1);
+}
+
+#[test]
+fn test_exploration_limits() {
+    let mut cfg = Config::default();
+    cfg.set_model_generation(true);
+    let ctx = Context::new(&cfg);
+
+    // Loops back to the start for as long as `a` is non-zero
+    let pgm = vec![push(int_var("a")), push(int(0)), jumpi(), stop()];
+    let machine = || BaseMachine::new_with_ctx(BaseStack::init(), &ctx);
+
+    let report = machine()
+        .with_limits(ExplorationLimits {
+            max_steps: Some(30),
+            ..Default::default()
+        })
+        .run_sym(&pgm)
+        .unwrap();
+    assert!(report
+        .reachable
+        .iter()
+        .any(|leaf| leaf.reason == TerminationReason::Halted));
+    assert!(report
+        .reachable
+        .iter()
+        .any(|leaf| leaf.reason == TerminationReason::StepLimit));
+
+    let report = machine()
+        .with_limits(ExplorationLimits {
+            max_depth: Some(3),
+            ..Default::default()
+        })
+        .run_sym(&pgm)
+        .unwrap();
+    let cut = report
+        .reachable
+        .iter()
+        .find(|leaf| leaf.reason == TerminationReason::DepthLimit)
+        .unwrap();
+    // The path taking the back edge a third time is cut before executing again
+    assert_eq!(cut.depth, 3);
+    assert_eq!(cut.pc, 0);
+    assert_eq!(cut.stack.peek::<Expr>(0).unwrap().as_u64().unwrap(), 0);
+
+    let report = machine()
+        .with_limits(ExplorationLimits {
+            max_paths: Some(2),
+            max_steps: Some(1000),
+            ..Default::default()
+        })
+        .run_sym(&pgm)
+        .unwrap();
+    assert!(report.len() <= 2);
+    assert!(!report.cut_off.is_empty());
+    assert!(report
+        .cut_off
+        .iter()
+        .all(|branch| branch.reason == TerminationReason::PathLimit && branch.model.is_none()));
+}
+
+#[derive(Default)]
+struct Counts {
+    steps: usize,
+    forks: usize,
+    leaves: usize,
+    queries: usize,
+}
+
+struct CountingObserver(Rc<std::cell::RefCell<Counts>>);
+
+impl<S, M> MachineObserver<S, M> for CountingObserver {
+    fn after_step(&mut self, _path_id: usize, _pc: usize, _stack: &S, _mem: &M) {
+        self.0.borrow_mut().steps += 1;
+    }
+
+    fn on_fork(&mut self, _path_id: usize, _pc: usize, _successors: &[Branch<S, M>]) {
+        self.0.borrow_mut().forks += 1;
+    }
+
+    fn on_leaf(&mut self, _outcome: &PathOutcome<S, M>) {
+        self.0.borrow_mut().leaves += 1;
+    }
+
+    fn on_solver_query(&mut self, _constraints: &[Expr], _result: SatResult) {
+        self.0.borrow_mut().queries += 1;
+    }
+}
+
+#[test]
+fn test_observer() {
+    let mut cfg = Config::default();
+    cfg.set_model_generation(true);
+    let ctx = Context::new(&cfg);
+
+    let counts = Rc::new(std::cell::RefCell::new(Counts::default()));
+    let stack: BaseStack<Expr> = BaseStack::init();
+    let machine =
+        BaseMachine::new_with_ctx(stack, &ctx).with_observer(CountingObserver(counts.clone()));
+    let pgm = vec![
+        push(int_var("a")),
+        push(int(4)),
+        jumpi(),
+        stop(),
+        push(int(1)),
+    ];
+
+    let report = machine.run_sym(&pgm).unwrap();
+    let counts = counts.borrow();
+
+    assert_eq!(counts.steps, report.stats.steps - 1);
+    assert_eq!(counts.forks, 1);
+    assert_eq!(counts.leaves, report.len());
+    assert_eq!(counts.queries, report.stats.solver_queries);
+}
+
+#[test]
+fn test_concrete_run() {
+    let mut cfg = Config::default();
+    cfg.set_model_generation(true);
+    let ctx = Context::new(&cfg);
+
+    let machine = || BaseMachine::new_with_ctx(BaseStack::init(), &ctx);
+    let pgm = vec![
+        push(int(7)),
+        push(int(0)),
+        mstore(),
+        push(int(0)),
+        mload(),
+        push(int(8)),
+        jumpi(),
+        push(int(100)),
+        stop(),
+        push(int(200)),
+    ];
+
+    let outcome = machine().run(&pgm).unwrap();
+
+    // The stored value is read back from the updated memory and the jump is taken
+    assert_eq!(outcome.reason, TerminationReason::Halted);
+    assert_eq!(outcome.pc, 8);
+    assert_eq!(outcome.trace, vec![0, 1, 2, 3, 4, 5, 6, 8]);
+    assert_eq!(outcome.stack.peek::<Expr>(0).unwrap().as_u64().unwrap(), 8);
+    assert_eq!(
+        outcome
+            .stack
+            .peek::<Expr>(1)
+            .unwrap()
+            .simplify()
+            .as_u64()
+            .unwrap(),
+        7
+    );
+
+    // Symbolic execution reaches the same final state
+    let report = machine().run_sym(&pgm).unwrap();
+    assert_eq!(report.reachable.len(), 1);
+    assert_eq!(report.reachable[0].trace, outcome.trace);
+
+    let pgm = vec![push(int_var("a")), push(int(3)), jumpi()];
+    assert!(machine().run(&pgm).is_err());
+}
+
+#[test]
+fn test_stack_errors() {
+    let mut cfg = Config::default();
+    cfg.set_model_generation(true);
+    let ctx = Context::new(&cfg);
+
+    let stack = BaseStack::<Expr>::init().with_max_depth(2);
+    assert_eq!(stack.pop().err(), Some(StackError::EmptyStack));
+    assert_eq!(stack.peek::<Expr>(0).err(), Some(StackError::EmptyStack));
+    let full = stack.push(int(1)).and_then(|s| s.push(int(2))).unwrap();
+    assert_eq!(full.len(), 2);
+    assert_eq!(full.peek::<Expr>(1), Ok(int(1)));
+    assert_eq!(full.peek::<Expr>(2).err(), Some(StackError::EmptyStack));
+    assert_eq!(full.push(int(3)).err(), Some(StackError::StackOverflow));
+
+    // Underflow while executing ends the path with an error
+    let machine = BaseMachine::new_with_ctx(BaseStack::init(), &ctx);
+    let report = machine.run_sym(&vec![push(int(1)), add()]).unwrap();
+    assert_eq!(
+        report.reachable[0].reason,
+        TerminationReason::Error(MachineError::Instruction {
+            pc: 1,
+            path_id: 0,
+            source: InstructionError::Stack(StackError::EmptyStack),
+        })
+    );
+
+    // So does overflow when the instruction's changes are applied
+    let machine = BaseMachine::new_with_ctx(BaseStack::init().with_max_depth(2), &ctx);
+    let pgm = vec![push(int(1)), push(int(2)), push(int(3))];
+    let report = machine.run_sym(&pgm).unwrap();
+    let leaf = &report.reachable[0];
+    assert_eq!(
+        leaf.reason,
+        TerminationReason::Error(MachineError::Stack {
+            pc: 2,
+            path_id: 0,
+            source: StackError::StackOverflow,
+        })
+    );
+    assert_eq!(leaf.stack.len(), 2);
+}
+
+#[test]
+fn test_persistent_stack() {
+    let base = BaseStack::<Expr>::init()
+        .push(int(1))
+        .and_then(|s| s.push(int(2)))
+        .unwrap();
+    // Both branches share the cells of `base`, which stays as it was
+    let left = base.push(int(3)).unwrap();
+    let right = base.pop().and_then(|s| s.push(int(4))).unwrap();
+    assert_eq!(base.len(), 2);
+    assert_eq!(base.peek::<Expr>(0), Ok(int(2)));
+    assert_eq!(
+        left.iter().cloned().collect::<Vec<_>>(),
+        vec![int(3), int(2), int(1),]
+    );
+    assert_eq!(right.len(), 2);
+    assert_eq!(right.peek::<Expr>(0), Ok(int(4)));
+    assert_eq!(right.peek::<Expr>(1), Ok(int(1)));
+    drop(base);
+    assert_eq!(left.peek::<Expr>(2), Ok(int(1)));
+
+    // Deep stacks are dropped without recursing
+    let deep = (0..100_000u64).try_fold(BaseStack::<u64>::init(), |s, i| s.push(i));
+    assert_eq!(deep.unwrap().len(), 100_000);
+}
+
+#[test]
+fn test_checked_pops() {
+    let mut cfg = Config::default();
+    cfg.set_model_generation(true);
+    let ctx = Context::new(&cfg);
+
+    let x = int_var("x");
+    let stack = BaseStack::<Expr>::init()
+        .push(int(2))
+        .and_then(|s| s.push(int(1)))
+        .unwrap();
+    // Pops recorded in the wrong order, as by an instruction peeking the wrong operands
+    let swapped = || StackRecord::<BaseStack<Expr>> {
+        changed: vec![StackOpRecord::Pop(int(2)), StackOpRecord::Pop(int(1))],
+    };
+    assert!(swapped().apply(stack.clone()).unwrap().is_empty());
+    assert_eq!(
+        swapped().apply_checked(stack.clone()).err(),
+        Some(StackError::PopMismatch {
+            expected: "2".to_string(),
+            actual: "1".to_string(),
+        })
+    );
+    // Both sides are folded before comparing
+    let in_order = StackRecord::<BaseStack<Expr>> {
+        changed: vec![
+            StackOpRecord::Pop(int(1)),
+            StackOpRecord::Pop(int(1) + int(1)),
+        ],
+    };
+    assert!(in_order.apply_checked(stack.clone()).unwrap().is_empty());
+    // A symbolic value that might equal the top counts as a match
+    let symbolic = StackRecord::<BaseStack<Expr>> {
+        changed: vec![StackOpRecord::Pop(x.clone())],
+    };
+    assert_eq!(symbolic.apply_checked(stack).unwrap().len(), 1);
+
+    // Correct instructions pass the check in both modes of execution
+    let machine = || BaseMachine::new_with_ctx(BaseStack::init(), &ctx).with_checked_stack();
+    let pgm = vec![
+        push(x.clone()),
+        push(int(0)),
+        mstore(),
+        push(int(0)),
+        mload(),
+        push(int(2)),
+        add(),
+        stop(),
+    ];
+    let outcome = machine().run(&pgm).unwrap();
+    assert_eq!(outcome.reason, TerminationReason::Halted);
+    let report = machine().run_sym(&pgm).unwrap();
+    assert_eq!(report.reachable.len(), 1);
+    assert_eq!(report.reachable[0].reason, TerminationReason::Halted);
+}
+
+#[test]
+fn test_errored_path_is_a_leaf() {
+    let mut cfg = Config::default();
+    cfg.set_model_generation(true);
+    let ctx = Context::new(&cfg);
+
+    let machine = || BaseMachine::new_with_ctx(BaseStack::init(), &ctx);
+    let pgm = vec![
+        push(int_var("a")),
+        push(int(5)),
+        jumpi(),
+        push(int(100)),
+        stop(),
+        invalid(),
+    ];
+
+    let report = machine().run_sym(&pgm).unwrap();
+
+    // The failing path does not stop the other one from being explored
+    assert_eq!(report.reachable.len(), 2);
+    let errored = report
+        .reachable
+        .iter()
+        .find(|leaf| matches!(leaf.reason, TerminationReason::Error(_)))
+        .unwrap();
+    assert_eq!(
+        errored.reason,
+        TerminationReason::Error(MachineError::Instruction {
+            pc: 5,
+            path_id: errored.path_id,
+            source: InstructionError::UnknownInstruction("INVALID".to_string()),
+        })
+    );
+    // The model says how to reach the error
+    let a = errored.model.as_ref().unwrap().eval(&int_var("a"));
+    assert_ne!(a.as_int().unwrap(), 0);
+
+    let pgm = vec![push(int(1)), invalid()];
+    assert!(matches!(
+        machine().run(&pgm),
+        Err(MachineError::Instruction { pc: 1, .. })
+    ));
+}
+
+#[test]
+fn test_stepping() {
+    let mut cfg = Config::default();
+    cfg.set_model_generation(true);
+    let ctx = Context::new(&cfg);
+
+    let stack: BaseStack<Expr> = BaseStack::init();
+    let mut machine = BaseMachine::new_with_ctx(stack, &ctx);
+    machine.load_program(vec![
+        push(int(1)),
+        push(int(2)),
+        add(),
+        push(int_var("a")),
+        push(int(7)),
+        jumpi(),
+        push(int(100)),
+        stop(),
+    ]);
+
+    assert_eq!(machine.step().unwrap(), None);
+    assert_eq!(machine.pc(), 1);
+    assert_eq!(
+        machine.stack().peek::<Expr>(0).unwrap().as_u64().unwrap(),
+        1
+    );
+
+    assert_eq!(machine.run_until(3).unwrap(), Pause::Breakpoint(3));
+    assert_eq!(
+        machine
+            .stack()
+            .peek::<Expr>(0)
+            .unwrap()
+            .simplify()
+            .as_u64()
+            .unwrap(),
+        3
+    );
+
+    machine.add_breakpoint(Breakpoint::Instruction(Box::new(|inst| {
+        *inst == Instruction::JUMPI
+    })));
+    assert_eq!(machine.resume().unwrap(), Pause::Breakpoint(5));
+    assert!(machine.constraints().is_empty());
+
+    // The symbolic branch follows the first feasible successor, falling through
+    assert_eq!(machine.step().unwrap(), None);
+    assert_eq!(machine.pc(), 6);
+    assert_eq!(machine.constraints().len(), 1);
+
+    assert_eq!(
+        machine.resume().unwrap(),
+        Pause::Terminated(TerminationReason::Halted)
+    );
+    assert_eq!(machine.pc(), 7);
+    assert_eq!(
+        machine.stack().peek::<Expr>(0).unwrap().as_u64().unwrap(),
+        100
+    );
+    assert_eq!(machine.step().unwrap(), Some(TerminationReason::Halted));
+}
+
+#[test]
+fn test_symbol_registry() {
+    let mut cfg = Config::default();
+    cfg.set_model_generation(true);
+    let ctx = Context::new(&cfg);
+
+    let mut symbols = SymbolRegistry::new("m1");
+    let x = symbols.int("x");
+    let mem = symbols.memory("memory", |name| {
+        BaseMemorySymbolicArray::named(name, Sort::Int, Sort::Int)
+    });
+    // Names never repeat, within a registry or across prefixes
+    assert_eq!(x.to_string(), "m1.x");
+    assert_eq!(symbols.fresh_name("x"), "m1.x.1");
+    let mut other = SymbolRegistry::new("m2");
+    assert_eq!(other.int("x").to_string(), "m2.x");
+
+    let machine = BaseMachine::new_with_ctx(BaseStack::init(), &ctx).with_memory(mem);
+    let pgm = vec![push(x), assert(int(3)), mload(), assert(int(7))];
+    let report = machine.run_sym(&pgm).unwrap();
+    assert_eq!(report.reachable.len(), 1);
+    let leaf = &report.reachable[0];
+
+    let bindings = symbols
+        .decode(leaf.model.as_ref().unwrap(), &leaf.constraints)
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>();
+    assert_eq!(bindings, vec!["x = 3", "memory[3] = 7"]);
+    assert!(leaf
+        .to_smtlib()
+        .contains("(declare-fun m1.memory () (Array Int Int))"));
+}
+
+#[test]
+fn test_stack_manipulation() {
+    // 4 on top of 3, 2 and 1
+    let stack = (1..=4u64)
+        .try_fold(BaseStack::<u64>::init().with_max_depth(5), |s, v| s.push(v))
+        .unwrap();
+    assert_eq!(stack.peek_range::<u64>(0..3), Ok(vec![4, 3, 2]));
+    assert_eq!(stack.peek_range::<u64>(2..4), Ok(vec![2, 1]));
+    assert_eq!(
+        stack.peek_range::<u64>(2..5).err(),
+        Some(StackError::EmptyStack)
+    );
+
+    let top_down = |s: &BaseStack<u64>| s.iter().copied().collect::<Vec<_>>();
+    assert_eq!(top_down(&stack.pop_n(3).unwrap()), vec![1]);
+    assert!(stack.pop_n(4).unwrap().is_empty());
+    assert_eq!(stack.pop_n(5).err(), Some(StackError::EmptyStack));
+    assert_eq!(top_down(&stack.dup(0).unwrap()), vec![4, 4, 3, 2, 1]);
+    assert_eq!(top_down(&stack.dup(3).unwrap()), vec![1, 4, 3, 2, 1]);
+    assert_eq!(stack.dup(4).err(), Some(StackError::EmptyStack));
+    assert_eq!(
+        stack.dup(0).and_then(|s| s.dup(0)).err(),
+        Some(StackError::StackOverflow)
+    );
+    assert_eq!(top_down(&stack.swap(1).unwrap()), vec![3, 4, 2, 1]);
+    assert_eq!(top_down(&stack.swap(3).unwrap()), vec![1, 3, 2, 4]);
+    assert_eq!(top_down(&stack.swap(0).unwrap()), vec![4, 3, 2, 1]);
+    assert_eq!(stack.swap(4).err(), Some(StackError::EmptyStack));
+    // None of this touched the original
+    assert_eq!(top_down(&stack), vec![4, 3, 2, 1]);
+
+    // The same operations as records, which can be checked and reverted
+    let record = || StackRecord::<BaseStack<u64>> {
+        changed: vec![
+            StackOpRecord::Dup(2),
+            StackOpRecord::Swap(2),
+            StackOpRecord::PopN(vec![3, 4]),
+        ],
+    };
+    let applied = record().apply_checked(stack.clone()).unwrap();
+    assert_eq!(top_down(&applied), vec![2, 2, 1]);
+    assert_eq!(
+        top_down(&record().revert(applied).unwrap()),
+        vec![4, 3, 2, 1]
+    );
+    let wrong = StackRecord::<BaseStack<u64>> {
+        changed: vec![StackOpRecord::PopN(vec![4, 2])],
+    };
+    assert_eq!(
+        wrong.apply_checked(stack).err(),
+        Some(StackError::PopMismatch {
+            expected: "2".to_string(),
+            actual: "3".to_string(),
+        })
+    );
 }
@@ -0,0 +1,178 @@
+use std::time::Duration;
+
+use symbolic_stack_machines::expr::model::Model;
+use symbolic_stack_machines::expr::{Expr, Sort};
+use symbolic_stack_machines::instructions::*;
+use symbolic_stack_machines::machine::error::MachineError;
+use symbolic_stack_machines::machine::limits::TerminationReason;
+use symbolic_stack_machines::machine::smtlib::SmtLibSolver;
+use symbolic_stack_machines::machine::solver::{SatResult, SolverBackend};
+use symbolic_stack_machines::machine::BaseMachine;
+use symbolic_stack_machines::memory::memory_models::BaseMemorySymbolicArray;
+use symbolic_stack_machines::stack::{BaseStack, Stack, StackOpRecord, StackRecord};
+
+// Just enough of an instruction set to fork a path
+#[derive(Clone, Debug)]
+enum Instruction {
+    Push(Expr),
+    JumpI,
+}
+
+impl VMInstruction for Instruction {
+    type ValStack = BaseStack<Expr>;
+
+    type Mem = BaseMemorySymbolicArray;
+
+    fn exec(
+        &self,
+        stack: &Self::ValStack,
+        _memory: &Self::Mem,
+    ) -> InstructionResult<ExecRecord<Self::ValStack, Self::Mem>> {
+        let mut change_log = ExecRecord {
+            stack_diff: None,
+            mem_diff: None,
+            successors: vec![],
+            halt: false,
+        };
+        match self {
+            Instruction::Push(v) => {
+                change_log.stack_diff = Some(StackRecord {
+                    changed: vec![StackOpRecord::Push(v.clone())],
+                });
+            }
+            Instruction::JumpI => {
+                let dest = stack.peek::<Expr>(0)?;
+                let cond = stack.peek::<Expr>(1)?;
+                let zero = Expr::int(0);
+                change_log.stack_diff = Some(StackRecord {
+                    changed: vec![
+                        StackOpRecord::Pop(dest.clone()),
+                        StackOpRecord::Pop(cond.clone()),
+                    ],
+                });
+                change_log.successors = vec![
+                    Successor::next(vec![cond._eq(&zero)]),
+                    Successor::jump(dest.as_u64().unwrap() as usize, vec![cond._eq(&zero).not()]),
+                ];
+            }
+        };
+        Ok(change_log)
+    }
+}
+
+// Decides queries by evaluating them under one fixed assignment, so a path is reachable
+// exactly when that assignment takes it
+struct FixedAssignment {
+    model: Model,
+    queries: usize,
+}
+
+impl SolverBackend for FixedAssignment {
+    fn check_path(&mut self, constraints: &[Expr]) -> Result<SatResult, String> {
+        self.queries += 1;
+        if constraints
+            .iter()
+            .all(|constraint| self.model.eval(constraint).as_bool() == Some(true))
+        {
+            Ok(SatResult::Sat)
+        } else {
+            Ok(SatResult::Unsat)
+        }
+    }
+
+    fn get_model(&self) -> Option<Model> {
+        Some(self.model.clone())
+    }
+
+    fn reason_unknown(&self) -> String {
+        String::new()
+    }
+
+    fn queries(&self) -> usize {
+        self.queries
+    }
+
+    fn set_timeout(&mut self, _timeout: Duration) {}
+}
+
+fn pgm() -> Vec<Instruction> {
+    vec![
+        Instruction::Push(Expr::var("a", Sort::Int)),
+        Instruction::Push(Expr::int(4)),
+        Instruction::JumpI,
+        Instruction::Push(Expr::int(0)),
+        Instruction::Push(Expr::int(1)),
+    ]
+}
+
+#[test]
+fn test_run_sym_with_solver() {
+    let a = Expr::var("a", Sort::Int);
+    let model = Model::from_values(std::slice::from_ref(&a), &[Expr::int(1)]);
+    let solver = FixedAssignment {
+        model: model.clone(),
+        queries: 0,
+    };
+    let machine: BaseMachine<_, _, Instruction, _, _, _> =
+        BaseMachine::new(BaseStack::init(), (Sort::Int, Sort::Int))
+            .unwrap()
+            .with_solver(solver);
+    let report = machine.run_sym(&pgm()).unwrap();
+
+    // Only the jump is taken when a = 1
+    assert_eq!(report.reachable.len(), 1);
+    assert_eq!(report.unreachable.len(), 1);
+    let leaf = &report.reachable[0];
+    assert_eq!(leaf.trace, [0, 1, 2, 4]);
+    assert_eq!(leaf.reason, TerminationReason::FellOffProgram);
+    assert_eq!(leaf.model, Some(model));
+    assert_eq!(leaf.stack.peek::<Expr>(0).unwrap(), Expr::int(1));
+    // One query for each side of the fork, and one for the reachable leaf
+    assert_eq!(report.stats.solver_queries, 3);
+}
+
+#[test]
+fn test_run_sym_without_solver() {
+    let machine: BaseMachine<_, _, Instruction, _, _, _> =
+        BaseMachine::new(BaseStack::init(), (Sort::Int, Sort::Int)).unwrap();
+    assert!(matches!(
+        machine.run_sym(&pgm()),
+        Err(MachineError::NoSolver)
+    ));
+}
+
+#[test]
+fn test_solver_failure() {
+    // Answers the two options set at start up, then exits before the first query
+    let exiting =
+        || SmtLibSolver::new("sh", &["-c", "read l; echo success; read l; echo success"]).unwrap();
+    let machine = || -> BaseMachine<_, _, Instruction, _, _, _> {
+        BaseMachine::new(BaseStack::init(), (Sort::Int, Sort::Int))
+            .unwrap()
+            .with_solver(exiting())
+    };
+    let report = machine().run_sym(&pgm()).unwrap();
+
+    // Both sides of the fork end with the solver's error instead of being pruned
+    assert!(report.reachable.is_empty() && report.unreachable.is_empty());
+    assert_eq!(report.unknown.len(), 2);
+    for leaf in &report.unknown {
+        assert!(matches!(
+            &leaf.reason,
+            TerminationReason::Error(MachineError::Solver { pc: 2, path_id, .. }) if *path_id == leaf.path_id
+        ));
+    }
+
+    // The debugger stops at the fork it could not check
+    let mut stepped = machine();
+    stepped.load_program(pgm());
+    assert!(matches!(
+        stepped.resume(),
+        Err(MachineError::Solver {
+            pc: 2,
+            path_id: 0,
+            ..
+        })
+    ));
+    assert_eq!(stepped.pc(), 2);
+}
@@ -0,0 +1,103 @@
+use symbolic_stack_machines::instructions::val::{
+    ConstantFold, HybridBool, HybridInner, HybridVal,
+};
+mod common;
+
+use common::{int, int_var, valid};
+
+#[test]
+fn test_concrete_stays_concrete() {
+    let a = HybridVal::concrete(12);
+    let b = HybridVal::concrete(5);
+
+    assert!(matches!((a.clone() + b.clone()).0, HybridInner::C(17)));
+    assert!(matches!((a.clone() - b.clone()).0, HybridInner::C(7)));
+    assert!(matches!((a.clone() * b.clone()).0, HybridInner::C(60)));
+    assert!(matches!((a.clone() / b.clone()).0, HybridInner::C(2)));
+    assert!(matches!((a.clone() % b.clone()).0, HybridInner::C(2)));
+    assert!(matches!((a.clone() & b.clone()).0, HybridInner::C(4)));
+    assert!(matches!((a.clone() | b.clone()).0, HybridInner::C(13)));
+    assert!(matches!((a.clone() ^ b.clone()).0, HybridInner::C(9)));
+    assert!(matches!((a.clone() << b.clone()).0, HybridInner::C(384)));
+    assert!(matches!(
+        (a.clone() >> HybridVal::concrete(64)).0,
+        HybridInner::C(0)
+    ));
+    assert!(matches!((!b.clone()).0, HybridInner::C(v) if v == !5));
+    assert!(matches!(a.gt(&b), HybridBool::C(true)));
+    assert!(matches!(a._eq(&b), HybridBool::C(false)));
+}
+
+#[test]
+fn test_mixed_operands_are_lifted() {
+    let x = HybridVal::symbolic(int_var("x"));
+    let three = HybridVal::concrete(3);
+
+    let sum = three.clone() + x.clone();
+    let HybridInner::S(sum) = &sum.0 else {
+        panic!("mixed addition should be symbolic");
+    };
+    let sum = sum.as_expr();
+    let masked = (x.clone() | HybridVal::concrete(1)) & HybridVal::concrete(0xff);
+
+    let is_0x1234 = x.to_expr()._eq(&int(0x1234));
+    assert!(valid(&is_0x1234.implies(&sum._eq(&int(0x1237)))));
+    assert!(valid(&is_0x1234.implies(&masked.to_expr()._eq(&int(0x35)))));
+
+    // Comparisons with a symbolic side need the solver
+    let below = x.lt(&three);
+    assert_eq!(below.as_bool(), None);
+    let picked = below.ite(&HybridInner::C(1), &HybridInner::C(0));
+    assert!(matches!(picked, HybridInner::S(_)));
+    assert!(matches!(
+        HybridBool::C(false).ite(&x.0, &three.0),
+        HybridInner::C(3)
+    ));
+}
+
+#[test]
+fn test_folding_makes_values_concrete() {
+    let seven = HybridVal::symbolic(int(7));
+
+    let sum = (seven + HybridVal::concrete(1)).fold_constants();
+    assert_eq!(sum.as_u64(), Some(8));
+    assert!(matches!(sum.0, HybridInner::C(8)));
+}
+
+#[test]
+fn test_out_of_range_results_are_lifted() {
+    let zero = HybridVal::concrete(0);
+    let one = HybridVal::concrete(1);
+    let max = HybridVal::concrete(u64::MAX);
+
+    // Results that do not fit a u64 become Int constants instead of wrapping or panicking
+    let below = zero.clone() - one.clone();
+    assert!(matches!(&below.0, HybridInner::S(v) if v.as_expr().as_int() == Some(-1)));
+    assert_eq!(below.as_u64(), None);
+    let above = max.clone() + one.clone();
+    assert!(matches!(&above.0, HybridInner::S(v) if v.as_expr().as_int() == Some(1 << 64)));
+    let product = max.clone() * HybridVal::concrete(2);
+    assert_eq!(product.to_expr().as_int(), Some(2 * u64::MAX as i128));
+    // and come back once the result fits again
+    let back = (below + one.clone()).fold_constants();
+    assert!(matches!(back.0, HybridInner::C(0)));
+
+    // Division by zero is left to the solver, which agrees with symbolic operands
+    let seven = HybridVal::concrete(7);
+    let quotient = seven.clone() / zero.clone();
+    let remainder = seven.clone() % zero.clone();
+    assert!(matches!(quotient.0, HybridInner::S(_)));
+    assert!(matches!(remainder.fold_constants().0, HybridInner::S(_)));
+    let y = HybridVal::symbolic(int_var("y"));
+    let is_zero = y.to_expr()._eq(&int(0));
+    assert!(valid(
+        &is_zero.implies(
+            &(seven.clone() / y.clone())
+                .to_expr()
+                ._eq(&quotient.to_expr())
+        )
+    ));
+    assert!(valid(
+        &is_zero.implies(&(seven % y).to_expr()._eq(&remainder.to_expr()))
+    ));
+}
@@ -0,0 +1,165 @@
+use symbolic_stack_machines::instructions::error::InstructionError;
+use symbolic_stack_machines::instructions::word::{Word256, Word32, Word8};
+use symbolic_stack_machines::instructions::*;
+use symbolic_stack_machines::machine::BaseMachine;
+use symbolic_stack_machines::memory::memory_models::BaseMemorySymbolicArray;
+use symbolic_stack_machines::memory::{MemOpRecord, MemRecord, ReadOnlyMem};
+use symbolic_stack_machines::stack::{BaseStack, Stack, StackOpRecord, StackRecord};
+
+use symbolic_stack_machines::expr::Sort;
+use z3::{Config, Context};
+mod common;
+
+use common::valid;
+
+#[derive(Clone, Debug)]
+enum Instruction {
+    Push(Word256),
+    Add,
+    MStore,
+    MLoad,
+    Assert(Word256),
+}
+
+impl VMInstruction for Instruction {
+    type ValStack = BaseStack<Word256>;
+
+    type Mem = BaseMemorySymbolicArray;
+
+    fn exec(
+        &self,
+        stack: &Self::ValStack,
+        memory: &Self::Mem,
+    ) -> InstructionResult<ExecRecord<Self::ValStack, Self::Mem>> {
+        let mut change_log = ExecRecord {
+            stack_diff: None,
+            mem_diff: None,
+            successors: vec![],
+            halt: false,
+        };
+        let top = || stack.peek::<Word256>(0).unwrap();
+        let second = || stack.peek::<Word256>(1).unwrap();
+        match self {
+            Instruction::Push(v) => {
+                change_log.stack_diff = Some(StackRecord {
+                    changed: vec![StackOpRecord::Push(v.clone())],
+                });
+            }
+            Instruction::Add => {
+                change_log.stack_diff = Some(StackRecord {
+                    changed: vec![
+                        StackOpRecord::Pop(top()),
+                        StackOpRecord::Pop(second()),
+                        StackOpRecord::Push(top() + second()),
+                    ],
+                });
+            }
+            Instruction::MStore => {
+                let offset = top();
+                let prev = memory
+                    .read(offset.clone().into())
+                    .map_err(|e| InstructionError::InstructionExecutionFailure(e.to_string()))?
+                    .unwrap();
+                change_log.stack_diff = Some(StackRecord {
+                    changed: vec![
+                        StackOpRecord::Pop(offset.clone()),
+                        StackOpRecord::Pop(second()),
+                    ],
+                });
+                change_log.mem_diff = Some(MemRecord {
+                    diff: vec![MemOpRecord::Write((offset.into(), prev, second().into()))],
+                });
+            }
+            Instruction::MLoad => {
+                let val = memory
+                    .read(top().into())
+                    .map_err(|e| InstructionError::InstructionExecutionFailure(e.to_string()))?
+                    .unwrap();
+                change_log.stack_diff = Some(StackRecord {
+                    changed: vec![
+                        StackOpRecord::Pop(top()),
+                        StackOpRecord::Push(Word256::from_bv(val)),
+                    ],
+                });
+            }
+            Instruction::Assert(v) => {
+                change_log.successors = vec![Successor::next(vec![top()._eq(v)])];
+            }
+        }
+        Ok(change_log)
+    }
+}
+
+#[test]
+fn test_wrapping_arithmetic() {
+    let byte = Word8::from_u64;
+
+    assert_eq!((byte(0xff) + byte(2)).as_u64(), Some(1));
+    assert_eq!((byte(1) - byte(2)).as_u64(), Some(0xff));
+    assert_eq!((byte(0x10) * byte(0x11)).as_u64(), Some(0x10));
+    assert_eq!(Word8::from_u64(0x1ff).as_u64(), Some(0xff));
+
+    // -7 / 2 and -7 % 2 on signed bytes
+    let minus_seven = byte(0xf9);
+    assert_eq!((minus_seven.clone() / byte(2)).as_u64(), Some(0x7c));
+    assert_eq!(minus_seven.sdiv(&byte(2)).as_u64(), Some(0xfd));
+    assert_eq!(minus_seven.srem(&byte(2)).as_u64(), Some(0xff));
+    assert_eq!(minus_seven.smod(&byte(2)).as_u64(), Some(1));
+
+    assert_eq!((byte(0x81) << byte(1)).as_u64(), Some(2));
+    assert_eq!((byte(0x81) >> byte(1)).as_u64(), Some(0x40));
+    assert_eq!(byte(0x81).sar(&byte(1)).as_u64(), Some(0xc0));
+    assert_eq!((byte(1) << byte(8)).as_u64(), Some(0));
+    assert_eq!((!byte(0x0f) ^ byte(0xff)).as_u64(), Some(0x0f));
+
+    assert_eq!(byte(0x80).ult(&byte(1)).simplify().as_bool(), Some(false));
+    assert_eq!(byte(0x80).slt(&byte(1)).simplify().as_bool(), Some(true));
+}
+
+#[test]
+fn test_bytes_and_widths() {
+    let word = Word32::from_u64(0x1234_5678);
+
+    assert_eq!(word.byte(0).as_u64(), Some(0x78));
+    assert_eq!(word.byte(3).as_u64(), Some(0x12));
+    assert_eq!(Word8::from_bv(word.bv().clone()).as_u64(), Some(0x78));
+
+    // A symbolic word at the maximum wraps around to 0
+    let x = Word256::new_const("x");
+    let max = !Word256::from_u64(0);
+    let one = Word256::from_u64(1);
+    assert!(valid(
+        &x._eq(&max)
+            .implies(&(x.clone() + one)._eq(&Word256::from_u64(0)))
+    ));
+    // Comparisons are unsigned unless stated otherwise
+    assert!(valid(&x.uge(&Word256::from_u64(0))));
+}
+
+#[test]
+fn test_words_on_stack_and_memory() {
+    let ctx = Context::new(&Config::default());
+    let word = Word256::from_u64;
+
+    let machine = BaseMachine::new(BaseStack::init(), (Sort::BitVec(256), Sort::BitVec(256)))
+        .unwrap()
+        .with_context(&ctx);
+    let max = !word(0);
+    let pgm = vec![
+        Instruction::Push(word(1)),
+        Instruction::Push(max),
+        Instruction::Add,
+        Instruction::Push(word(32)),
+        Instruction::MStore,
+        Instruction::Push(word(32)),
+        Instruction::MLoad,
+        // The sum overflowed, so only 0 is feasible
+        Instruction::Assert(word(0)),
+    ];
+
+    let report = machine.run_sym(&pgm).unwrap();
+    assert_eq!(report.reachable.len(), 1);
+    assert_eq!(report.unreachable.len(), 0);
+    let top = report.reachable[0].stack.peek::<Word256>(0).unwrap();
+    assert_eq!(top.as_u64(), Some(0));
+}